@@ -1,58 +1,35 @@
-use std::{fmt::Write, net::SocketAddr, println, task::Context, task::Poll, time::Duration};
+#![allow(clippy::needless_return)]
+use std::{fmt::Write, net::SocketAddr, time::Duration};
 
 use anyhow::Result;
 use axtel::{
-    http::{request::Path, response::IntoResponse},
+    http::{
+        body::BoxBody,
+        request::{Path, Request, State},
+        response::{IntoResponse, Response},
+    },
     json::Json,
+    middleware::{
+        from_fn::{from_fn_with_state, Next},
+        handle_error::default_error_handler,
+        rate_limit::{Quota, RateLimitLayer},
+        security_headers::{CspNonce, SecurityHeadersLayer},
+        timeout::TimeoutLayer,
+        trace::TraceLayer,
+    },
     router::{method_router::get, Router},
 };
 use serde::{Deserialize, Serialize};
 use tokio::net::TcpListener;
-use tower::{
-    limit::{RateLimit, RateLimitLayer},
-    timeout::TimeoutLayer,
-    Layer, Service,
-};
 
-pub struct LogLayer {
-    target: &'static str,
-}
-
-impl<S> Layer<S> for LogLayer {
-    type Service = LogService<S>;
-
-    fn layer(&self, service: S) -> Self::Service {
-        LogService {
-            target: self.target,
-            service,
-        }
-    }
-}
-
-// This service implements the Log behavior
-pub struct LogService<S> {
-    target: &'static str,
-    service: S,
-}
-
-impl<S, Request> Service<Request> for LogService<S>
-where
-    S: Service<Request>,
-    Request: std::fmt::Debug,
-{
-    type Response = S::Response;
-    type Error = S::Error;
-    type Future = S::Future;
-
-    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.service.poll_ready(cx)
-    }
-
-    fn call(&mut self, request: Request) -> Self::Future {
-        // Insert log statement here or other functionality
-        println!("request = {:?}, target = {:?}", request, self.target);
-        self.service.call(request)
-    }
+// logs every request together with the target the middleware was created with
+async fn log(
+    State(target): State<&'static str>,
+    request: Request<BoxBody>,
+    next: Next,
+) -> Response {
+    println!("request = {:?}, target = {:?}", request, target);
+    return next.run(request).await;
 }
 
 #[derive(Deserialize, Serialize)]
@@ -89,8 +66,12 @@ async fn print_json(Json(data): Json<Test>) -> impl IntoResponse {
     dbg!(data);
 }
 
-async fn html() -> impl IntoResponse {
-    tokio::fs::read_to_string("index.html").await.unwrap()
+async fn html(nonce: CspNonce) -> impl IntoResponse {
+    let html = tokio::fs::read_to_string("index.html").await.unwrap();
+    return Response::builder()
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(html.replace("{nonce}", nonce.as_str()))
+        .unwrap();
 }
 
 async fn date() -> impl IntoResponse {
@@ -107,6 +88,10 @@ async fn empty() -> () {}
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .init();
+
     let router = Router::new()
         .route("/", get(empty))
         .route("/hello", get(hello))
@@ -115,18 +100,19 @@ async fn main() -> Result<()> {
         .route("/user", get(create_user))
         .route("/json", get(print_json))
         .route("/date", get(date))
-        .route("/loop", get(loop_inf))
+        .route("/loop", get(loop_inf).timeout(Duration::from_millis(500)))
         .route("/complex", get(complex))
+        .layer(TraceLayer::new())
+        .layer(SecurityHeadersLayer::new())
         .layer(TimeoutLayer::new(Duration::new(1, 0)))
-        .layer(RateLimitLayer::new(100, Duration::new(1, 0)))
-        .layer(LogLayer {
-            target: "axtel test",
-        })
+        .layer(RateLimitLayer::new(Quota::per_second(100)))
+        .layer(from_fn_with_state("axtel test", log))
+        .handle_error(default_error_handler)
         .service();
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
     let listener = TcpListener::bind(addr).await?;
-    println!("listening on: {}", addr);
+    tracing::info!(%addr, "listening");
 
     axtel::server::serve(listener, router).await?;
     Ok(())
@@ -0,0 +1,57 @@
+use std::{
+    error::Error,
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use http_body_util::BodyExt;
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+
+pub type BoxError = Box<dyn Error + Send + Sync>;
+
+/// the body of requests as they are passed through the middleware stack, before the router
+/// collects it into a [`Body`](crate::http::request::Body).
+///
+/// connections of every protocol produce this type, so the same service can serve all of them
+pub struct BoxBody(http_body_util::combinators::BoxBody<Bytes, BoxError>);
+
+pub fn boxed<B>(body: B) -> BoxBody
+where
+    B: Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: Into<BoxError>,
+{
+    return BoxBody(body.map_err(Into::into).boxed());
+}
+
+impl Default for BoxBody {
+    fn default() -> Self {
+        return boxed(http_body_util::Empty::new());
+    }
+}
+
+impl fmt::Debug for BoxBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoxBody").finish_non_exhaustive()
+    }
+}
+
+impl Body for BoxBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.0).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.0.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.0.size_hint()
+    }
+}
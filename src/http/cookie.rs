@@ -0,0 +1,481 @@
+use anyhow::Result;
+use hyper::{
+    header::{self, HeaderMap, HeaderValue},
+    http::request::Parts,
+};
+
+use crate::http::{
+    request::{FromRequestParts, State},
+    response::{IntoResponse, IntoResponseParts, Response},
+};
+
+pub use ::cookie::{time, Cookie, Expiration, SameSite};
+
+/// the key used to sign and encrypt cookies of [`SignedCookieJar`] and [`PrivateCookieJar`],
+/// which is taken from the [`State`] of the request, see
+/// [`StateLayer`](crate::middleware::state::StateLayer).
+///
+/// keys can be rotated by keeping the old key as a previous one, cookies using it are still
+/// accepted. they aren't sent again with the new key automatically, because the request only
+/// carries their value and not the path, domain or expiry they were set with, instead
+/// [`SignedCookieJar::rotated_names`] and [`PrivateCookieJar::rotated_names`] tell which ones the
+/// handler should add again
+///
+/// ```ignore
+/// let key = Key::derive_from(new_secret).with_previous(Key::derive_from(old_secret));
+/// let router = Router::new()
+///     .route("/", get(handler))
+///     .layer(StateLayer::new(key));
+/// ```
+#[derive(Clone)]
+pub struct Key {
+    current: ::cookie::Key,
+    previous: Vec<::cookie::Key>,
+}
+
+impl Key {
+    /// uses 64 bytes of `key`, the first half for signing and the second half for encryption
+    ///
+    /// # Panics
+    /// panics if `key` is shorter than 64 bytes
+    pub fn new(key: &[u8]) -> Self {
+        return Self {
+            current: ::cookie::Key::from(key),
+            previous: Vec::new(),
+        };
+    }
+
+    /// derives the keys from a master key of at least 32 bytes
+    ///
+    /// # Panics
+    /// panics if `master` is shorter than 32 bytes
+    pub fn derive_from(master: &[u8]) -> Self {
+        return Self {
+            current: ::cookie::Key::derive_from(master),
+            previous: Vec::new(),
+        };
+    }
+
+    /// a random key, cookies can't be read anymore once the server restarts
+    pub fn generate() -> Self {
+        return Self {
+            current: ::cookie::Key::generate(),
+            previous: Vec::new(),
+        };
+    }
+
+    /// keeps accepting cookies of `previous` and all of its previous keys
+    pub fn with_previous(mut self, previous: Key) -> Self {
+        self.previous.push(previous.current);
+        self.previous.extend(previous.previous);
+        return self;
+    }
+}
+
+fn parse_cookies(headers: &HeaderMap) -> impl Iterator<Item = Cookie<'static>> + '_ {
+    return headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(Cookie::split_parse)
+        .filter_map(|cookie| cookie.ok())
+        .map(Cookie::into_owned);
+}
+
+fn set_cookies(jar: &::cookie::CookieJar, res: &mut Response) {
+    for cookie in jar.delta() {
+        match HeaderValue::from_str(&cookie.to_string()) {
+            Ok(value) => {
+                res.headers_mut().append(header::SET_COOKIE, value);
+            }
+            Err(_) => {
+                tracing::warn!(cookie = cookie.name(), "cookie can't be sent in a header");
+            }
+        }
+    }
+}
+
+/// the cookies of the request, returning the jar sends the added and removed cookies as
+/// `Set-Cookie` headers
+///
+/// ```ignore
+/// async fn handler(jar: CookieJar) -> impl IntoResponse {
+///     let visits = jar.get("visits").and_then(|c| c.value().parse().ok()).unwrap_or(0);
+///     (jar.add(Cookie::new("visits", (visits + 1).to_string())), "hello")
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CookieJar {
+    jar: ::cookie::CookieJar,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut jar = ::cookie::CookieJar::new();
+        for cookie in parse_cookies(headers) {
+            jar.add_original(cookie);
+        }
+        return Self { jar };
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Cookie<'static>> {
+        return self.jar.get(name);
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn add<C: Into<Cookie<'static>>>(mut self, cookie: C) -> Self {
+        self.jar.add(cookie);
+        return self;
+    }
+
+    /// tells the client to delete the cookie, the path and domain have to match the ones it was
+    /// set with
+    pub fn remove<C: Into<Cookie<'static>>>(mut self, cookie: C) -> Self {
+        self.jar.remove(cookie);
+        return self;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie<'static>> {
+        return self.jar.iter();
+    }
+}
+
+impl FromRequestParts for CookieJar {
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        return Ok(Self::from_headers(&parts.headers));
+    }
+}
+
+impl IntoResponseParts for CookieJar {
+    fn into_response_parts(self, res: &mut Response) {
+        set_cookies(&self.jar, res);
+    }
+}
+
+impl IntoResponse for CookieJar {
+    fn into_response(self) -> Response {
+        return (self, ()).into_response();
+    }
+}
+
+/// a [`CookieJar`] whose cookies are signed with the [`Key`] from the state, so clients can read
+/// but not change them. cookies with a missing or wrong signature are ignored
+#[derive(Clone)]
+pub struct SignedCookieJar {
+    jar: ::cookie::CookieJar,
+    key: Key,
+    rotated: Vec<String>,
+}
+
+impl SignedCookieJar {
+    pub fn new(key: Key) -> Self {
+        return Self {
+            jar: ::cookie::CookieJar::new(),
+            key,
+            rotated: Vec::new(),
+        };
+    }
+
+    pub fn from_headers(headers: &HeaderMap, key: Key) -> Self {
+        let mut jar = ::cookie::CookieJar::new();
+        let mut rotated = Vec::new();
+        for cookie in parse_cookies(headers) {
+            if jar.signed(&key.current).verify(cookie.clone()).is_some() {
+                jar.add_original(cookie);
+                continue;
+            }
+            let verified = key
+                .previous
+                .iter()
+                .find_map(|previous| jar.signed(previous).verify(cookie.clone()));
+            if let Some(verified) = verified {
+                // kept signed with the current key, so it reads like any other cookie
+                let name = verified.name().to_string();
+                let mut current = ::cookie::CookieJar::new();
+                current.signed_mut(&key.current).add(verified);
+                jar.add_original(current.get(&name).cloned().unwrap());
+                rotated.push(name);
+            }
+        }
+        return Self { jar, key, rotated };
+    }
+
+    /// whether any cookie was signed with a previous key, see [`Self::rotated_names`]
+    pub fn needs_rotation(&self) -> bool {
+        return !self.rotated.is_empty();
+    }
+
+    /// the names of the cookies signed with a previous key, adding them again sends them
+    /// signed with the current one
+    pub fn rotated_names(&self) -> impl Iterator<Item = &str> {
+        return self.rotated.iter().map(String::as_str);
+    }
+
+    /// the cookie with its signature removed
+    pub fn get(&self, name: &str) -> Option<Cookie<'static>> {
+        return self.jar.signed(&self.key.current).get(name);
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn add<C: Into<Cookie<'static>>>(mut self, cookie: C) -> Self {
+        self.jar.signed_mut(&self.key.current).add(cookie);
+        return self;
+    }
+
+    pub fn remove<C: Into<Cookie<'static>>>(mut self, cookie: C) -> Self {
+        self.jar.remove(cookie);
+        return self;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Cookie<'static>> + '_ {
+        return self.jar.iter().filter_map(|cookie| self.get(cookie.name()));
+    }
+}
+
+impl FromRequestParts for SignedCookieJar {
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        let State(key) = State::<Key>::from_request_parts(parts)?;
+        return Ok(Self::from_headers(&parts.headers, key));
+    }
+}
+
+impl IntoResponseParts for SignedCookieJar {
+    fn into_response_parts(self, res: &mut Response) {
+        set_cookies(&self.jar, res);
+    }
+}
+
+impl IntoResponse for SignedCookieJar {
+    fn into_response(self) -> Response {
+        return (self, ()).into_response();
+    }
+}
+
+/// a [`CookieJar`] whose cookies are encrypted with the [`Key`] from the state, so clients can
+/// neither read nor change them. cookies which can't be decrypted are ignored
+#[derive(Clone)]
+pub struct PrivateCookieJar {
+    jar: ::cookie::CookieJar,
+    key: Key,
+    rotated: Vec<String>,
+}
+
+impl PrivateCookieJar {
+    pub fn new(key: Key) -> Self {
+        return Self {
+            jar: ::cookie::CookieJar::new(),
+            key,
+            rotated: Vec::new(),
+        };
+    }
+
+    pub fn from_headers(headers: &HeaderMap, key: Key) -> Self {
+        let mut jar = ::cookie::CookieJar::new();
+        let mut rotated = Vec::new();
+        for cookie in parse_cookies(headers) {
+            if jar.private(&key.current).decrypt(cookie.clone()).is_some() {
+                jar.add_original(cookie);
+                continue;
+            }
+            let decrypted = key
+                .previous
+                .iter()
+                .find_map(|previous| jar.private(previous).decrypt(cookie.clone()));
+            if let Some(decrypted) = decrypted {
+                // kept encrypted with the current key, so it reads like any other cookie
+                let name = decrypted.name().to_string();
+                let mut current = ::cookie::CookieJar::new();
+                current.private_mut(&key.current).add(decrypted);
+                jar.add_original(current.get(&name).cloned().unwrap());
+                rotated.push(name);
+            }
+        }
+        return Self { jar, key, rotated };
+    }
+
+    /// whether any cookie was encrypted with a previous key, see [`Self::rotated_names`]
+    pub fn needs_rotation(&self) -> bool {
+        return !self.rotated.is_empty();
+    }
+
+    /// the names of the cookies encrypted with a previous key, adding them again sends them
+    /// encrypted with the current one
+    pub fn rotated_names(&self) -> impl Iterator<Item = &str> {
+        return self.rotated.iter().map(String::as_str);
+    }
+
+    /// the decrypted cookie
+    pub fn get(&self, name: &str) -> Option<Cookie<'static>> {
+        return self.jar.private(&self.key.current).get(name);
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn add<C: Into<Cookie<'static>>>(mut self, cookie: C) -> Self {
+        self.jar.private_mut(&self.key.current).add(cookie);
+        return self;
+    }
+
+    pub fn remove<C: Into<Cookie<'static>>>(mut self, cookie: C) -> Self {
+        self.jar.remove(cookie);
+        return self;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Cookie<'static>> + '_ {
+        return self.jar.iter().filter_map(|cookie| self.get(cookie.name()));
+    }
+}
+
+impl FromRequestParts for PrivateCookieJar {
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        let State(key) = State::<Key>::from_request_parts(parts)?;
+        return Ok(Self::from_headers(&parts.headers, key));
+    }
+}
+
+impl IntoResponseParts for PrivateCookieJar {
+    fn into_response_parts(self, res: &mut Response) {
+        set_cookies(&self.jar, res);
+    }
+}
+
+impl IntoResponse for PrivateCookieJar {
+    fn into_response(self) -> Response {
+        return (self, ()).into_response();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(secret: u8) -> Key {
+        return Key::derive_from(&[secret; 32]);
+    }
+
+    /// the `Cookie` header a client would send back after receiving `res`
+    fn cookie_header(res: &Response) -> HeaderMap {
+        let cookies: Vec<_> = res
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|value| {
+                let cookie = Cookie::parse(value.to_str().unwrap()).unwrap();
+                return format!("{}={}", cookie.name(), cookie.value());
+            })
+            .collect();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, cookies.join("; ").parse().unwrap());
+        return headers;
+    }
+
+    fn tamper(headers: &HeaderMap) -> HeaderMap {
+        let value = headers[header::COOKIE].to_str().unwrap();
+        let (rest, last) = value.split_at(value.len() - 1);
+        let last = if last == "A" { "B" } else { "A" };
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, format!("{}{}", rest, last).parse().unwrap());
+        return headers;
+    }
+
+    #[test]
+    fn signed() {
+        let res = SignedCookieJar::new(key(1))
+            .add(Cookie::new("user", "alice"))
+            .into_response();
+        let headers = cookie_header(&res);
+        // the value can be read by the client
+        assert!(headers[header::COOKIE].to_str().unwrap().ends_with("alice"));
+
+        let jar = SignedCookieJar::from_headers(&headers, key(1));
+        assert_eq!(jar.get("user").unwrap().value(), "alice");
+        assert!(!jar.needs_rotation());
+        // unchanged cookies aren't sent again
+        assert!(jar
+            .into_response()
+            .headers()
+            .get(header::SET_COOKIE)
+            .is_none());
+
+        let mut forged = HeaderMap::new();
+        forged.insert(header::COOKIE, "user=mallory".parse().unwrap());
+        assert!(SignedCookieJar::from_headers(&forged, key(1))
+            .get("user")
+            .is_none());
+        let tampered = headers[header::COOKIE]
+            .to_str()
+            .unwrap()
+            .replace("alice", "alicf");
+        forged.insert(header::COOKIE, tampered.parse().unwrap());
+        assert!(SignedCookieJar::from_headers(&forged, key(1))
+            .get("user")
+            .is_none());
+        assert!(SignedCookieJar::from_headers(&headers, key(2))
+            .get("user")
+            .is_none());
+    }
+
+    #[test]
+    fn signed_rotation() {
+        let res = SignedCookieJar::new(key(1))
+            .add(Cookie::new("user", "alice"))
+            .into_response();
+        let headers = cookie_header(&res);
+
+        let jar = SignedCookieJar::from_headers(&headers, key(2).with_previous(key(1)));
+        assert_eq!(jar.get("user").unwrap().value(), "alice");
+        assert!(jar.needs_rotation());
+        assert_eq!(jar.rotated_names().collect::<Vec<_>>(), ["user"]);
+        // the cookie isn't re-issued unless the handler adds it again
+        let res = jar.clone().into_response();
+        assert!(res.headers().get(header::SET_COOKIE).is_none());
+
+        let user = jar.get("user").unwrap();
+        let res = jar.add(user).into_response();
+        let jar = SignedCookieJar::from_headers(&cookie_header(&res), key(2));
+        assert_eq!(jar.get("user").unwrap().value(), "alice");
+        assert!(!jar.needs_rotation());
+    }
+
+    #[test]
+    fn private() {
+        let res = PrivateCookieJar::new(key(1))
+            .add(Cookie::new("user", "alice"))
+            .into_response();
+        let headers = cookie_header(&res);
+        assert!(!headers[header::COOKIE].to_str().unwrap().contains("alice"));
+
+        let jar = PrivateCookieJar::from_headers(&headers, key(1));
+        assert_eq!(jar.get("user").unwrap().value(), "alice");
+        assert!(!jar.needs_rotation());
+
+        assert!(PrivateCookieJar::from_headers(&tamper(&headers), key(1))
+            .get("user")
+            .is_none());
+        assert!(PrivateCookieJar::from_headers(&headers, key(2))
+            .get("user")
+            .is_none());
+    }
+
+    #[test]
+    fn private_rotation() {
+        let res = PrivateCookieJar::new(key(1))
+            .add(Cookie::new("user", "alice"))
+            .into_response();
+        let headers = cookie_header(&res);
+
+        let jar = PrivateCookieJar::from_headers(&headers, key(2).with_previous(key(1)));
+        assert_eq!(jar.get("user").unwrap().value(), "alice");
+        assert_eq!(jar.rotated_names().collect::<Vec<_>>(), ["user"]);
+        let res = jar.clone().into_response();
+        assert!(res.headers().get(header::SET_COOKIE).is_none());
+
+        let user = jar.get("user").unwrap();
+        let res = jar.add(user).into_response();
+        let jar = PrivateCookieJar::from_headers(&cookie_header(&res), key(2));
+        assert_eq!(jar.get("user").unwrap().value(), "alice");
+    }
+}
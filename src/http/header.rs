@@ -0,0 +1,102 @@
+use anyhow::{anyhow, Result};
+use headers::{Header, HeaderMapExt};
+use hyper::{http::request::Parts, StatusCode};
+
+use crate::http::{
+    request::{FromRequestParts, Rejection},
+    response::{IntoResponse, IntoResponseParts, Response},
+};
+
+/// extracts a typed header from the request, or sets it on the response.
+///
+/// requests without the header, or with a value that can't be parsed, are rejected with
+/// `400 Bad Request`, use `Option<TypedHeader<T>>` for optional headers, which still rejects
+/// invalid values. the header types are
+/// re-exported from the [`headers`](crate::headers) crate
+///
+/// ```ignore
+/// async fn handler(
+///     TypedHeader(user_agent): TypedHeader<UserAgent>,
+///     etag: Option<TypedHeader<IfNoneMatch>>,
+/// ) -> impl IntoResponse {
+///     (TypedHeader(ContentType::text()), user_agent.to_string())
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TypedHeader<T>(pub T);
+
+impl<T> FromRequestParts for TypedHeader<T>
+where
+    T: Header,
+{
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        let mut values = parts.headers.get_all(T::name()).iter().peekable();
+        if values.peek().is_none() {
+            let message = format!("missing header `{}`", T::name());
+            return Err(Rejection::new(StatusCode::BAD_REQUEST, message)
+                .missing()
+                .into());
+        }
+        return T::decode(&mut values)
+            .map(TypedHeader)
+            .map_err(|err| anyhow!("invalid header `{}`: {}", T::name(), err));
+    }
+}
+
+impl<T> IntoResponseParts for TypedHeader<T>
+where
+    T: Header,
+{
+    fn into_response_parts(self, res: &mut Response) {
+        res.headers_mut().typed_insert(self.0);
+    }
+}
+
+impl<T> IntoResponse for TypedHeader<T>
+where
+    T: Header,
+{
+    fn into_response(self) -> Response {
+        let mut res = ().into_response();
+        self.into_response_parts(&mut res);
+        return res;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use headers::ContentLength;
+    use hyper::{header, Request};
+
+    fn parts(content_length: Option<&str>) -> Parts {
+        let mut req = Request::builder();
+        if let Some(value) = content_length {
+            req = req.header(header::CONTENT_LENGTH, value);
+        }
+        return req.body(()).unwrap().into_parts().0;
+    }
+
+    #[test]
+    fn typed_header() {
+        let TypedHeader(len) =
+            TypedHeader::<ContentLength>::from_request_parts(&parts(Some("3"))).unwrap();
+        assert_eq!(len.0, 3);
+        assert!(TypedHeader::<ContentLength>::from_request_parts(&parts(None)).is_err());
+        assert!(TypedHeader::<ContentLength>::from_request_parts(&parts(Some("x"))).is_err());
+    }
+
+    #[test]
+    fn optional_typed_header() {
+        let extract =
+            |value| Option::<TypedHeader<ContentLength>>::from_request_parts(&parts(value));
+        assert_eq!(
+            extract(Some("3")).unwrap(),
+            Some(TypedHeader(ContentLength(3)))
+        );
+        assert_eq!(extract(None).unwrap(), None);
+        // an invalid header is still rejected instead of being treated as missing
+        let err = extract(Some("x")).unwrap_err();
+        assert_eq!(err.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -1,2 +1,5 @@
+pub mod body;
+pub mod cookie;
+pub mod header;
 pub mod request;
 pub mod response;
@@ -1,5 +1,9 @@
-use anyhow::Result;
-use hyper::http::{self, request::Parts};
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use hyper::http::{self, header::HeaderName, request::Parts, HeaderMap, HeaderValue, StatusCode};
+
+use crate::http::response::{IntoResponse, Response};
 
 pub type Method = http::Method;
 
@@ -9,6 +13,27 @@ impl FromRequestParts for Method {
     }
 }
 
+impl FromRequestParts for HeaderMap {
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        return Ok(parts.headers.clone());
+    }
+}
+
+/// `None` if the request doesn't have `T` at all, see [`Rejection::missing`]. values which are
+/// there but invalid still reject the request
+impl<T> FromRequestParts for Option<T>
+where
+    T: FromRequestParts,
+{
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        return match T::from_request_parts(parts) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if err.downcast_ref().map_or(false, Rejection::is_missing) => Ok(None),
+            Err(err) => Err(err),
+        };
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Path(pub String);
 
@@ -18,6 +43,24 @@ impl FromRequestParts for Path {
     }
 }
 
+/// state shared with handlers and middleware, see [`StateLayer`](crate::middleware::state::StateLayer)
+/// and [`from_fn_with_state`](crate::middleware::from_fn::from_fn_with_state)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct State<T>(pub T);
+
+impl<T> FromRequestParts for State<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        return parts
+            .extensions
+            .get::<State<T>>()
+            .cloned()
+            .ok_or_else(|| anyhow!("missing state of type {}", std::any::type_name::<T>()));
+    }
+}
+
 pub type Body = String;
 
 impl FromRequest for Body {
@@ -35,6 +78,70 @@ impl FromRequest for Request {
     }
 }
 
+/// an error of an extractor with its own status and headers, other errors reject the request
+/// with `400 Bad Request`
+///
+/// ```ignore
+/// return Err(Rejection::new(StatusCode::UNAUTHORIZED, "missing token")
+///     .header(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"))
+///     .into());
+/// ```
+#[derive(Clone, Debug)]
+pub struct Rejection {
+    status: StatusCode,
+    headers: HeaderMap,
+    message: String,
+    missing: bool,
+}
+
+impl Rejection {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        return Self {
+            status,
+            headers: HeaderMap::new(),
+            message: message.into(),
+            missing: false,
+        };
+    }
+
+    /// marks the rejection as caused by a value the request doesn't have, e.g. a missing header,
+    /// which `Option<T>` extracts as `None`
+    pub fn missing(mut self) -> Self {
+        self.missing = true;
+        return self;
+    }
+
+    pub fn is_missing(&self) -> bool {
+        return self.missing;
+    }
+
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        return self;
+    }
+
+    pub fn status(&self) -> StatusCode {
+        return self.status;
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        let mut res = Response::new(self.message);
+        *res.status_mut() = self.status;
+        *res.headers_mut() = self.headers;
+        return res;
+    }
+}
+
 pub trait FromRequest
 where
     Self: Sized,
@@ -1,5 +1,7 @@
 use hyper::http;
-use hyper::http::StatusCode;
+use hyper::http::{HeaderMap, StatusCode};
+
+use crate::http::request::Rejection;
 
 pub type Response<T = String> = http::Response<T>;
 
@@ -7,6 +9,46 @@ pub trait IntoResponse {
     fn into_response(self) -> Response;
 }
 
+/// adds to a response, like headers, and can be returned in a tuple before the response
+///
+/// ```ignore
+/// async fn handler() -> impl IntoResponse {
+///     (TypedHeader(ContentType::html()), "<h1>hello</h1>")
+/// }
+/// ```
+pub trait IntoResponseParts {
+    fn into_response_parts(self, res: &mut Response);
+}
+
+impl IntoResponseParts for HeaderMap {
+    fn into_response_parts(self, res: &mut Response) {
+        res.headers_mut().extend(self);
+    }
+}
+
+macro_rules! impl_into_response_for_parts {
+    ($($ty:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<$($ty,)* R> IntoResponse for ($($ty,)* R)
+        where
+            $($ty: IntoResponseParts,)*
+            R: IntoResponse,
+        {
+            fn into_response(self) -> Response {
+                let ($($ty,)* res) = self;
+                let mut res = res.into_response();
+                $($ty.into_response_parts(&mut res);)*
+                return res;
+            }
+        }
+    };
+}
+
+impl_into_response_for_parts!(T1);
+impl_into_response_for_parts!(T1, T2);
+impl_into_response_for_parts!(T1, T2, T3);
+impl_into_response_for_parts!(T1, T2, T3, T4);
+
 impl IntoResponse for Response {
     fn into_response(self) -> Response {
         self
@@ -72,9 +114,13 @@ impl IntoResponse for &str {
 
 impl IntoResponse for anyhow::Error {
     fn into_response(self) -> Response {
+        let err = match self.downcast::<Rejection>() {
+            Ok(rejection) => return rejection.into_response(),
+            Err(err) => err,
+        };
         return http::Response::builder()
             .status(StatusCode::BAD_REQUEST)
-            .body(self.to_string())
+            .body(err.to_string())
             .unwrap();
     }
 }
@@ -5,3 +5,5 @@ pub mod json;
 pub mod middleware;
 pub mod router;
 pub mod server;
+
+pub use headers;
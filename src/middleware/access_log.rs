@@ -0,0 +1,590 @@
+use std::{
+    fmt::Write as _,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc as std_mpsc, Arc, Mutex, PoisonError,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use hyper::{
+    body::{Body, Frame, SizeHint},
+    header, Request, Response,
+};
+use tokio::{io::AsyncWrite, io::AsyncWriteExt, sync::mpsc};
+use tower::{Layer, Service};
+
+use crate::server::connect_info::ConnectInfo;
+
+/// the format of the lines written by [`AccessLogLayer`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// the Common Log Format:
+    /// `127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] "GET /index.html HTTP/1.1" 200 2326`
+    #[default]
+    Common,
+    /// the Common Log Format followed by the quoted referer and user agent
+    Combined,
+    /// one json object per line, which also contains the duration in milliseconds
+    Json,
+}
+
+/// how many lines [`WriterSink`] and [`AsyncWriterSink`] buffer before dropping new ones
+pub const BUFFERED_LINES: usize = 8192;
+
+/// where the access log lines go, `line` has no trailing newline.
+///
+/// `write_line` is called from the response body while it is polled or dropped, so it must not
+/// block
+pub trait AccessLogSink: Send + Sync + 'static {
+    fn write_line(&self, line: &str);
+
+    /// the number of lines that were dropped because the sink couldn't keep up
+    fn dropped(&self) -> u64 {
+        return 0;
+    }
+}
+
+/// hands the lines to a thread writing them to a blocking writer, e.g. a file or stdout.
+///
+/// once [`BUFFERED_LINES`] lines are waiting to be written, new ones are dropped and counted
+/// instead of blocking the request
+pub struct WriterSink {
+    tx: std_mpsc::SyncSender<String>,
+    dropped: AtomicU64,
+}
+
+impl WriterSink {
+    /// spawns the thread writing to `writer`, it exits once the sink is dropped
+    pub fn new<W>(mut writer: W) -> Self
+    where
+        W: io::Write + Send + 'static,
+    {
+        let (tx, rx) = std_mpsc::sync_channel::<String>(BUFFERED_LINES);
+        std::thread::Builder::new()
+            .name("access-log".to_string())
+            .spawn(move || {
+                while let Ok(line) = rx.recv() {
+                    // write whatever queued up in the meantime before flushing
+                    let lines = std::iter::once(line).chain(rx.try_iter());
+                    if let Err(err) = write_lines(&mut writer, lines) {
+                        tracing::warn!(error = %err, "failed to write access log");
+                    }
+                }
+            })
+            .expect("failed to spawn the access log thread");
+        return Self {
+            tx,
+            dropped: AtomicU64::new(0),
+        };
+    }
+}
+
+fn write_lines<W: io::Write>(
+    writer: &mut W,
+    lines: impl Iterator<Item = String>,
+) -> io::Result<()> {
+    for line in lines {
+        writeln!(writer, "{}", line)?;
+    }
+    return writer.flush();
+}
+
+impl AccessLogSink for WriterSink {
+    fn write_line(&self, line: &str) {
+        if self.tx.try_send(line.to_string()).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn dropped(&self) -> u64 {
+        return self.dropped.load(Ordering::Relaxed);
+    }
+}
+
+/// hands the lines to a task writing them to an async writer.
+///
+/// once [`BUFFERED_LINES`] lines are waiting to be written, new ones are dropped and counted
+/// instead of blocking the request
+pub struct AsyncWriterSink {
+    tx: mpsc::Sender<String>,
+    dropped: AtomicU64,
+}
+
+impl AsyncWriterSink {
+    /// spawns the task writing to `writer`, so it has to be called inside of a tokio runtime
+    pub fn new<W>(mut writer: W) -> Self
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let (tx, mut rx) = mpsc::channel::<String>(BUFFERED_LINES);
+        tokio::spawn(async move {
+            while let Some(mut line) = rx.recv().await {
+                line.push('\n');
+                let res = async {
+                    writer.write_all(line.as_bytes()).await?;
+                    writer.flush().await
+                };
+                if let Err(err) = res.await {
+                    tracing::warn!(error = %err, "failed to write access log");
+                }
+            }
+        });
+        return Self {
+            tx,
+            dropped: AtomicU64::new(0),
+        };
+    }
+}
+
+impl AccessLogSink for AsyncWriterSink {
+    fn write_line(&self, line: &str) {
+        if self.tx.try_send(line.to_string()).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn dropped(&self) -> u64 {
+        return self.dropped.load(Ordering::Relaxed);
+    }
+}
+
+impl<F> AccessLogSink for F
+where
+    F: Fn(&str) + Send + Sync + 'static,
+{
+    fn write_line(&self, line: &str) {
+        self(line)
+    }
+}
+
+/// writes one line per completed request, once the whole response body was sent or the client
+/// went away, so the bytes and duration include streamed bodies.
+///
+/// the remote address is taken from [`ConnectInfo`], so the layer should wrap the service passed
+/// to [`serve`](crate::server::serve)
+///
+/// ```ignore
+/// let router = Router::new()
+///     .route("/", get(hello))
+///     .layer(AccessLogLayer::new(LogFormat::Combined).writer(File::create("access.log")?));
+/// ```
+#[derive(Clone)]
+pub struct AccessLogLayer {
+    format: LogFormat,
+    /// `None` writes to stdout
+    sink: Option<Arc<dyn AccessLogSink>>,
+}
+
+/// the sink of every layer writing to stdout, created once it is needed so layers with a sink
+/// of their own don't start a thread
+fn stdout_sink() -> Arc<dyn AccessLogSink> {
+    static STDOUT: Mutex<Option<Arc<WriterSink>>> = Mutex::new(None);
+    let mut sink = STDOUT.lock().unwrap_or_else(PoisonError::into_inner);
+    return sink
+        .get_or_insert_with(|| Arc::new(WriterSink::new(io::stdout())))
+        .clone();
+}
+
+impl AccessLogLayer {
+    /// writes lines of `format` to stdout through a [`WriterSink`]
+    pub fn new(format: LogFormat) -> Self {
+        return Self { format, sink: None };
+    }
+
+    pub fn sink<S: AccessLogSink>(mut self, sink: S) -> Self {
+        self.sink = Some(Arc::new(sink));
+        return self;
+    }
+
+    /// see [`WriterSink::new`]
+    pub fn writer<W: io::Write + Send + 'static>(self, writer: W) -> Self {
+        return self.sink(WriterSink::new(writer));
+    }
+
+    /// see [`AsyncWriterSink::new`]
+    pub fn async_writer<W: AsyncWrite + Unpin + Send + 'static>(self, writer: W) -> Self {
+        return self.sink(AsyncWriterSink::new(writer));
+    }
+
+    /// the number of lines the sink dropped because it couldn't keep up
+    pub fn dropped(&self) -> u64 {
+        return self.resolve_sink().dropped();
+    }
+
+    fn resolve_sink(&self) -> Arc<dyn AccessLogSink> {
+        return self.sink.clone().unwrap_or_else(stdout_sink);
+    }
+}
+
+impl<S> Layer<S> for AccessLogLayer {
+    type Service = AccessLog<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return AccessLog {
+            inner,
+            format: self.format,
+            sink: self.resolve_sink(),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct AccessLog<S> {
+    inner: S,
+    format: LogFormat,
+    sink: Arc<dyn AccessLogSink>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for AccessLog<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<LoggedBody<ResBody>>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let header = |name| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let mut entry = Entry {
+            format: self.format,
+            remote_addr: req
+                .extensions()
+                .get::<ConnectInfo>()
+                .map(|info| info.remote_addr.ip().to_string()),
+            time: SystemTime::now(),
+            start: Instant::now(),
+            method: req.method().to_string(),
+            target: req
+                .uri()
+                .path_and_query()
+                .map(|target| target.to_string())
+                .unwrap_or_else(|| req.uri().to_string()),
+            version: req.version(),
+            referer: header(header::REFERER),
+            user_agent: header(header::USER_AGENT),
+            status: 0,
+            bytes: 0,
+        };
+        let sink = self.sink.clone();
+
+        let fut = self.inner.call(req);
+        return Box::pin(async move {
+            let res = fut.await?;
+            entry.status = res.status().as_u16();
+            return Ok(res.map(|body| LoggedBody {
+                inner: body,
+                entry: Some(entry),
+                sink,
+            }));
+        });
+    }
+}
+
+struct Entry {
+    format: LogFormat,
+    remote_addr: Option<String>,
+    time: SystemTime,
+    start: Instant,
+    method: String,
+    target: String,
+    version: hyper::Version,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    status: u16,
+    bytes: u64,
+}
+
+impl Entry {
+    fn format(&self, duration: Duration) -> String {
+        let request_line = format!("{} {} {:?}", self.method, self.target, self.version);
+        if self.format == LogFormat::Json {
+            return serde_json::json!({
+                "remote_addr": self.remote_addr,
+                "time": rfc3339(self.time),
+                "method": self.method,
+                "path": self.target,
+                "version": format!("{:?}", self.version),
+                "status": self.status,
+                "bytes": self.bytes,
+                "referer": self.referer,
+                "user_agent": self.user_agent,
+                "duration_ms": duration.as_secs_f64() * 1000.0,
+            })
+            .to_string();
+        }
+
+        let mut line = String::new();
+        let bytes = match self.bytes {
+            0 => "-".to_string(),
+            bytes => bytes.to_string(),
+        };
+        let _ = write!(
+            line,
+            "{} - - [{}] \"{}\" {} {}",
+            self.remote_addr.as_deref().unwrap_or("-"),
+            clf_time(self.time),
+            escape(&request_line),
+            self.status,
+            bytes
+        );
+        if self.format == LogFormat::Combined {
+            let _ = write!(
+                line,
+                " \"{}\" \"{}\"",
+                escape(self.referer.as_deref().unwrap_or("-")),
+                escape(self.user_agent.as_deref().unwrap_or("-"))
+            );
+        }
+        return line;
+    }
+}
+
+/// escapes quotes, backslashes and control characters like apache does
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\x{:02x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
+/// the utc date and time of `time` as (year, month, day, hour, minute, second)
+fn civil_time(time: SystemTime) -> (i64, u32, u32, u64, u64, u64) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+
+    // days since 1970-01-01 to a gregorian date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    return (year, month, day, rem / 3600, rem % 3600 / 60, rem % 60);
+}
+
+fn clf_time(time: SystemTime) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let (year, month, day, hour, minute, second) = civil_time(time);
+    return format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        hour,
+        minute,
+        second
+    );
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = civil_time(time);
+    return format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    );
+}
+
+/// counts the bytes of the response body and writes the log line once it is done or dropped
+pub struct LoggedBody<B> {
+    inner: B,
+    entry: Option<Entry>,
+    sink: Arc<dyn AccessLogSink>,
+}
+
+impl<B> LoggedBody<B> {
+    fn finish(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            self.sink.write_line(&entry.format(entry.start.elapsed()));
+        }
+    }
+}
+
+impl<B> Drop for LoggedBody<B> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+impl<B> Body for LoggedBody<B>
+where
+    B: Body + Unpin,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let (Some(data), Some(entry)) = (frame.data_ref(), self.entry.as_mut()) {
+                    entry.bytes += hyper::body::Buf::remaining(data) as u64;
+                }
+            }
+            Poll::Ready(None) => self.finish(),
+            _ => (),
+        }
+        return poll;
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use std::convert::Infallible;
+    use tower::{service_fn, ServiceExt};
+
+    fn at(secs: u64) -> SystemTime {
+        return UNIX_EPOCH + Duration::from_secs(secs);
+    }
+
+    fn entry(format: LogFormat) -> Entry {
+        return Entry {
+            format,
+            remote_addr: Some("127.0.0.1".to_string()),
+            time: at(971186136),
+            start: Instant::now(),
+            method: "GET".to_string(),
+            target: "/index.html?q=\"x\"".to_string(),
+            version: hyper::Version::HTTP_11,
+            referer: None,
+            user_agent: Some("curl/8.0".to_string()),
+            status: 200,
+            bytes: 2326,
+        };
+    }
+
+    #[test]
+    fn civil_times() {
+        assert_eq!(civil_time(at(0)), (1970, 1, 1, 0, 0, 0));
+        assert_eq!(civil_time(at(951782400)), (2000, 2, 29, 0, 0, 0));
+        assert_eq!(civil_time(at(946684799)), (1999, 12, 31, 23, 59, 59));
+        assert_eq!(civil_time(at(1735689599)), (2024, 12, 31, 23, 59, 59));
+        assert_eq!(clf_time(at(971186136)), "10/Oct/2000:13:55:36 +0000");
+        assert_eq!(rfc3339(at(971186136)), "2000-10-10T13:55:36Z");
+    }
+
+    #[test]
+    fn common() {
+        let line = entry(LogFormat::Common).format(Duration::from_millis(5));
+        assert_eq!(
+            line,
+            r#"127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] "GET /index.html?q=\"x\" HTTP/1.1" 200 2326"#
+        );
+
+        let mut entry = entry(LogFormat::Common);
+        entry.remote_addr = None;
+        entry.bytes = 0;
+        entry.target = "/\n".to_string();
+        assert_eq!(
+            entry.format(Duration::ZERO),
+            r#"- - - [10/Oct/2000:13:55:36 +0000] "GET /\x0a HTTP/1.1" 200 -"#
+        );
+    }
+
+    #[test]
+    fn combined() {
+        let line = entry(LogFormat::Combined).format(Duration::from_millis(5));
+        assert_eq!(
+            line,
+            r#"127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] "GET /index.html?q=\"x\" HTTP/1.1" 200 2326 "-" "curl/8.0""#
+        );
+    }
+
+    #[test]
+    fn json() {
+        let line = entry(LogFormat::Json).format(Duration::from_millis(5));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "remote_addr": "127.0.0.1",
+                "time": "2000-10-10T13:55:36Z",
+                "method": "GET",
+                "path": "/index.html?q=\"x\"",
+                "version": "HTTP/1.1",
+                "status": 200,
+                "bytes": 2326,
+                "referer": null,
+                "user_agent": "curl/8.0",
+                "duration_ms": 5.0,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn written_once_the_body_is_done() {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let sink = {
+            let lines = lines.clone();
+            move |line: &str| lines.lock().unwrap().push(line.to_string())
+        };
+        let service = service_fn(|_: Request<String>| async {
+            let res = Response::builder()
+                .status(201)
+                .body("hello".to_string())
+                .unwrap();
+            return Ok::<_, Infallible>(res);
+        });
+        let req = Request::post("/users").body(String::new()).unwrap();
+        let res = AccessLogLayer::new(LogFormat::Common)
+            .sink(sink)
+            .layer(service)
+            .oneshot(req)
+            .await
+            .unwrap();
+        assert!(lines.lock().unwrap().is_empty());
+
+        res.into_body().collect().await.unwrap();
+        let lines = lines.lock().unwrap();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("- - - ["), "{}", lines[0]);
+        assert!(
+            lines[0].ends_with(r#"] "POST /users HTTP/1.1" 201 5"#),
+            "{}",
+            lines[0]
+        );
+    }
+}
@@ -0,0 +1,361 @@
+use std::{
+    fmt,
+    future::Future,
+    marker::PhantomData,
+    mem,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use anyhow::Result;
+use headers::{
+    authorization::{Basic, Bearer},
+    Authorization, HeaderMapExt,
+};
+use hyper::{
+    header::{self, HeaderName, HeaderValue},
+    http::request::Parts,
+    Request, StatusCode,
+};
+use tower::{Layer, Service};
+
+use crate::http::{
+    request::{FromRequestParts, Rejection},
+    response::{IntoResponse, Response},
+};
+
+/// credentials which can be checked by [`RequireAuthLayer`]
+pub trait Credentials: FromRequestParts + Send + 'static {
+    /// the `WWW-Authenticate` challenge sent when the credentials are missing or invalid
+    fn challenge() -> HeaderValue;
+}
+
+fn unauthorized<C: Credentials>(message: &str) -> Rejection {
+    return Rejection::new(StatusCode::UNAUTHORIZED, message)
+        .header(header::WWW_AUTHENTICATE, C::challenge());
+}
+
+/// the token of an `Authorization: Bearer <token>` header, requests without one are rejected with
+/// `401 Unauthorized`
+#[derive(Clone, PartialEq, Eq)]
+pub struct BearerToken(pub String);
+
+impl fmt::Debug for BearerToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BearerToken(..)")
+    }
+}
+
+impl FromRequestParts for BearerToken {
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        if !parts.headers.contains_key(header::AUTHORIZATION) {
+            return Err(unauthorized::<Self>("missing bearer token")
+                .missing()
+                .into());
+        }
+        let Some(auth) = parts.headers.typed_get::<Authorization<Bearer>>() else {
+            return Err(unauthorized::<Self>("invalid header `authorization`").into());
+        };
+        return Ok(Self(auth.token().to_string()));
+    }
+}
+
+impl Credentials for BearerToken {
+    fn challenge() -> HeaderValue {
+        return HeaderValue::from_static("Bearer");
+    }
+}
+
+/// the username and password of an `Authorization: Basic` header, requests without one are
+/// rejected with `401 Unauthorized`, which makes browsers ask for them
+#[derive(Clone, PartialEq, Eq)]
+pub struct BasicAuth {
+    pub username: String,
+    pub password: String,
+}
+
+impl fmt::Debug for BasicAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BasicAuth")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FromRequestParts for BasicAuth {
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        if !parts.headers.contains_key(header::AUTHORIZATION) {
+            return Err(unauthorized::<Self>("missing credentials").missing().into());
+        }
+        let Some(auth) = parts.headers.typed_get::<Authorization<Basic>>() else {
+            return Err(unauthorized::<Self>("invalid header `authorization`").into());
+        };
+        return Ok(Self {
+            username: auth.username().to_string(),
+            password: auth.password().to_string(),
+        });
+    }
+}
+
+impl Credentials for BasicAuth {
+    fn challenge() -> HeaderValue {
+        return HeaderValue::from_static("Basic realm=\"restricted\", charset=\"UTF-8\"");
+    }
+}
+
+/// the key of an `X-Api-Key` header, requests without one are rejected with `401 Unauthorized`
+#[derive(Clone, PartialEq, Eq)]
+pub struct ApiKey(pub String);
+
+impl ApiKey {
+    pub const HEADER: HeaderName = HeaderName::from_static("x-api-key");
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ApiKey(..)")
+    }
+}
+
+impl FromRequestParts for ApiKey {
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        let Some(key) = parts.headers.get(Self::HEADER) else {
+            return Err(unauthorized::<Self>("missing header `x-api-key`")
+                .missing()
+                .into());
+        };
+        let Ok(key) = key.to_str() else {
+            return Err(unauthorized::<Self>("invalid header `x-api-key`").into());
+        };
+        return Ok(Self(key.to_string()));
+    }
+}
+
+impl Credentials for ApiKey {
+    fn challenge() -> HeaderValue {
+        return HeaderValue::from_static("ApiKey header=\"x-api-key\"");
+    }
+}
+
+/// why the validator of [`RequireAuthLayer`] refused a request
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthError {
+    /// the credentials are wrong, answered with `401 Unauthorized` and a challenge
+    Unauthorized,
+    /// the credentials are right but not allowed to access the route, answered with
+    /// `403 Forbidden`
+    Forbidden,
+}
+
+/// whoever made the request, as returned by the validator of [`RequireAuthLayer`]
+#[derive(Clone, Debug)]
+pub struct Principal<P>(pub P);
+
+impl<P> FromRequestParts for Principal<P>
+where
+    P: Clone + Send + Sync + 'static,
+{
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        return parts
+            .extensions
+            .get::<Principal<P>>()
+            .cloned()
+            .ok_or_else(|| {
+                // the route is missing the layer, which isn't the fault of the client
+                tracing::error!(
+                    "missing principal of type {}, is the RequireAuthLayer applied?",
+                    std::any::type_name::<P>()
+                );
+                Rejection::new(StatusCode::INTERNAL_SERVER_ERROR, "internal server error").into()
+            });
+    }
+}
+
+/// only lets requests through whose credentials `C` are accepted by the validator, which
+/// returns the principal handlers can extract with [`Principal`].
+///
+/// missing or rejected credentials are answered with `401 Unauthorized` and the challenge of
+/// `C`, principals which aren't allowed with `403 Forbidden`
+///
+/// ```ignore
+/// let router = Router::new()
+///     .route("/admin", get(admin))
+///     .layer(RequireAuthLayer::new(|BearerToken(token)| async move {
+///         let user = db.user_by_token(&token).await.ok_or(AuthError::Unauthorized)?;
+///         if !user.is_admin {
+///             return Err(AuthError::Forbidden);
+///         }
+///         Ok(user)
+///     }));
+/// ```
+pub struct RequireAuthLayer<C, F> {
+    validator: F,
+    _credentials: PhantomData<fn() -> C>,
+}
+
+impl<C, F> RequireAuthLayer<C, F> {
+    pub fn new<Fut, P>(validator: F) -> Self
+    where
+        F: Fn(C) -> Fut,
+        Fut: Future<Output = Result<P, AuthError>>,
+    {
+        return Self {
+            validator,
+            _credentials: PhantomData,
+        };
+    }
+}
+
+impl<C, F: Clone> Clone for RequireAuthLayer<C, F> {
+    fn clone(&self) -> Self {
+        return Self {
+            validator: self.validator.clone(),
+            _credentials: PhantomData,
+        };
+    }
+}
+
+impl<S, C, F: Clone> Layer<S> for RequireAuthLayer<C, F> {
+    type Service = RequireAuth<S, C, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return RequireAuth {
+            inner,
+            validator: self.validator.clone(),
+            _credentials: PhantomData,
+        };
+    }
+}
+
+pub struct RequireAuth<S, C, F> {
+    inner: S,
+    validator: F,
+    _credentials: PhantomData<fn() -> C>,
+}
+
+impl<S: Clone, C, F: Clone> Clone for RequireAuth<S, C, F> {
+    fn clone(&self) -> Self {
+        return Self {
+            inner: self.inner.clone(),
+            validator: self.validator.clone(),
+            _credentials: PhantomData,
+        };
+    }
+}
+
+impl<S, B, C, F, Fut, P> Service<Request<B>> for RequireAuth<S, C, F>
+where
+    S: Service<Request<B>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+    C: Credentials,
+    F: Fn(C) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Result<P, AuthError>> + Send,
+    P: Clone + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        // the ready service has to handle the request, the clone waits for the next one
+        let clone = self.inner.clone();
+        let mut inner = mem::replace(&mut self.inner, clone);
+        let validator = self.validator.clone();
+        return Box::pin(async move {
+            let (mut parts, body) = req.into_parts();
+            let credentials = match C::from_request_parts(&parts) {
+                Ok(credentials) => credentials,
+                Err(err) => {
+                    tracing::debug!(error = %err, "rejected credentials");
+                    return Ok(err.into_response());
+                }
+            };
+            match (validator)(credentials).await {
+                Ok(principal) => {
+                    parts.extensions.insert(Principal(principal));
+                }
+                Err(AuthError::Unauthorized) => {
+                    tracing::debug!("invalid credentials");
+                    return Ok(unauthorized::<C>("invalid credentials").into_response());
+                }
+                Err(AuthError::Forbidden) => {
+                    tracing::debug!("forbidden");
+                    let res = Rejection::new(StatusCode::FORBIDDEN, "forbidden").into_response();
+                    return Ok(res);
+                }
+            }
+            return inner.call(Request::from_parts(parts, body)).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tower::{service_fn, ServiceExt};
+
+    async fn call(authorization: Option<&str>) -> Response {
+        let service = service_fn(|req: Request<String>| async move {
+            let (parts, _) = req.into_parts();
+            let res = match Principal::<String>::from_request_parts(&parts) {
+                Ok(Principal(user)) => Response::new(user),
+                Err(err) => err.into_response(),
+            };
+            return Ok::<_, Infallible>(res);
+        });
+        let layer = RequireAuthLayer::new(|BearerToken(token)| async move {
+            return match token.as_str() {
+                "admin" => Ok("admin".to_string()),
+                "guest" => Err(AuthError::Forbidden),
+                _ => Err(AuthError::Unauthorized),
+            };
+        });
+        let mut req = Request::new(String::new());
+        if let Some(value) = authorization {
+            let value = HeaderValue::from_str(value).unwrap();
+            req.headers_mut().insert(header::AUTHORIZATION, value);
+        }
+        return layer.layer(service).oneshot(req).await.unwrap();
+    }
+
+    fn assert_unauthorized(res: &Response) {
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(res.headers()[header::WWW_AUTHENTICATE], "Bearer");
+    }
+
+    #[tokio::test]
+    async fn authorized() {
+        let res = call(Some("Bearer admin")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body(), "admin");
+    }
+
+    #[tokio::test]
+    async fn unauthorized() {
+        assert_unauthorized(&call(None).await);
+        assert_unauthorized(&call(Some("Basic YTpi")).await);
+        assert_unauthorized(&call(Some("Bearer wrong")).await);
+    }
+
+    #[tokio::test]
+    async fn forbidden() {
+        let res = call(Some("Bearer guest")).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert!(res.headers().get(header::WWW_AUTHENTICATE).is_none());
+    }
+
+    #[test]
+    fn missing_principal() {
+        let (parts, _) = Request::new(()).into_parts();
+        let err = Principal::<String>::from_request_parts(&parts).unwrap_err();
+        let res = err.into_response();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(res.body(), "internal server error");
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    any::Any,
+    future::Future,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_util::FutureExt;
+use hyper::{Request, StatusCode};
+use tower::{Layer, Service};
+
+use crate::{http::response::Response, router::panic_message};
+
+type PanicHandler = Arc<dyn Fn(Box<dyn Any + Send>) -> Response + Send + Sync>;
+
+/// the response the router sends when a handler panicked
+pub(crate) fn default_panic_response() -> Response {
+    return Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body("internal server error".to_string())
+        .unwrap();
+}
+
+/// turns panics of the inner service into a `500 Internal Server Error` and logs the panic
+/// message.
+///
+/// the router already does this for handlers, the layer is needed to catch panics of middleware
+/// or to customize the response
+///
+/// ```ignore
+/// let router = Router::new()
+///     .route("/", get(hello))
+///     .layer(CatchPanicLayer::custom(|payload| {
+///         Response::builder().status(500).body("oops".to_string()).unwrap()
+///     }));
+/// ```
+#[derive(Clone)]
+pub struct CatchPanicLayer {
+    handler: PanicHandler,
+}
+
+impl Default for CatchPanicLayer {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl CatchPanicLayer {
+    pub fn new() -> Self {
+        return Self::custom(|_| default_panic_response());
+    }
+
+    /// responds with the response `f` creates from the panic payload
+    pub fn custom<F>(f: F) -> Self
+    where
+        F: Fn(Box<dyn Any + Send>) -> Response + Send + Sync + 'static,
+    {
+        return Self {
+            handler: Arc::new(f),
+        };
+    }
+}
+
+impl<S> Layer<S> for CatchPanicLayer {
+    type Service = CatchPanic<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return CatchPanic {
+            inner,
+            handler: self.handler.clone(),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct CatchPanic<S> {
+    inner: S,
+    handler: PanicHandler,
+}
+
+fn on_panic(handler: &PanicHandler, payload: Box<dyn Any + Send>) -> Response {
+    tracing::error!(panic = panic_message(payload.as_ref()), "service panicked");
+    return handler(payload);
+}
+
+impl<S, B> Service<Request<B>> for CatchPanic<S>
+where
+    S: Service<Request<B>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let handler = self.handler.clone();
+        let fut = match std::panic::catch_unwind(AssertUnwindSafe(|| self.inner.call(req))) {
+            Ok(fut) => fut,
+            Err(payload) => {
+                let res = on_panic(&handler, payload);
+                return Box::pin(async move { Ok(res) });
+            }
+        };
+        return Box::pin(async move {
+            match AssertUnwindSafe(fut).catch_unwind().await {
+                Ok(res) => res,
+                Err(payload) => Ok(on_panic(&handler, payload)),
+            }
+        });
+    }
+}
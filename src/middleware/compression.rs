@@ -0,0 +1,684 @@
+use std::{
+    future::Future,
+    io::{self, Read, Write},
+    mem,
+    pin::Pin,
+    sync::Arc,
+};
+
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper::{
+    body::{Body, Bytes},
+    header::{self, HeaderMap, HeaderValue},
+    Method, Request, Response, StatusCode,
+};
+use tower::{Layer, Service};
+
+use crate::http::body::{self, BoxBody, BoxError};
+
+/// bodies of at least this size are compressed on the blocking thread pool instead of stalling
+/// the other tasks of the runtime
+const BLOCKING_MIN_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    // the order in which encodings with the same quality are picked
+    const PREFERENCE: [Encoding; 4] = [
+        Encoding::Brotli,
+        Encoding::Zstd,
+        Encoding::Gzip,
+        Encoding::Deflate,
+    ];
+
+    fn as_str(self) -> &'static str {
+        return match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        };
+    }
+
+    fn from_content_encoding(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?.trim();
+        return match value.to_ascii_lowercase().as_str() {
+            "br" => Some(Encoding::Brotli),
+            "zstd" => Some(Encoding::Zstd),
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            _ => None,
+        };
+    }
+
+    fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        return match self {
+            Encoding::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 4, 22);
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                Ok(encoder.into_inner())
+            }
+            Encoding::Zstd => zstd::bulk::compress(data, 3),
+            Encoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Encoding::Deflate => {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        };
+    }
+
+    fn decoder<'a>(self, data: &'a [u8]) -> io::Result<Box<dyn Read + 'a>> {
+        return Ok(match self {
+            Encoding::Brotli => Box::new(brotli::Decompressor::new(data, 4096)),
+            Encoding::Zstd => Box::new(zstd::stream::Decoder::new(data)?),
+            Encoding::Gzip => Box::new(flate2::read::MultiGzDecoder::new(data)),
+            Encoding::Deflate => Box::new(flate2::read::ZlibDecoder::new(data)),
+        });
+    }
+}
+
+type ContentTypePredicate = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// compresses response bodies with the best encoding the client accepts out of brotli, zstd,
+/// gzip and deflate.
+///
+/// the body is buffered to compress it, so this should be the outermost layer
+#[derive(Clone)]
+pub struct CompressionLayer {
+    encodings: Vec<Encoding>,
+    min_size: usize,
+    predicate: ContentTypePredicate,
+}
+
+impl Default for CompressionLayer {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl CompressionLayer {
+    /// enables every encoding and compresses responses of at least 256 bytes whose content type
+    /// is not already compressed
+    pub fn new() -> Self {
+        return Self {
+            encodings: Encoding::PREFERENCE.to_vec(),
+            min_size: 256,
+            predicate: Arc::new(is_compressible),
+        };
+    }
+
+    fn encoding(mut self, encoding: Encoding, enable: bool) -> Self {
+        self.encodings.retain(|e| *e != encoding);
+        if enable {
+            self.encodings.push(encoding);
+        }
+        return self;
+    }
+
+    pub fn br(self, enable: bool) -> Self {
+        return self.encoding(Encoding::Brotli, enable);
+    }
+
+    pub fn zstd(self, enable: bool) -> Self {
+        return self.encoding(Encoding::Zstd, enable);
+    }
+
+    pub fn gzip(self, enable: bool) -> Self {
+        return self.encoding(Encoding::Gzip, enable);
+    }
+
+    pub fn deflate(self, enable: bool) -> Self {
+        return self.encoding(Encoding::Deflate, enable);
+    }
+
+    /// responses smaller than `min_size` bytes are sent as they are
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        return self;
+    }
+
+    /// only compresses responses whose `Content-Type` `f` returns true for, responses without
+    /// one are passed as an empty string
+    pub fn compress_when<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.predicate = Arc::new(f);
+        return self;
+    }
+
+    /// the accepted encoding with the highest quality, ties are broken by `Encoding::PREFERENCE`
+    fn negotiate(&self, headers: &HeaderMap) -> Option<Encoding> {
+        let mut accepted: Vec<(&str, f32)> = Vec::new();
+        for value in headers.get_all(header::ACCEPT_ENCODING) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            for item in value.split(',') {
+                let mut params = item.split(';');
+                let coding = params.next().unwrap_or_default().trim();
+                let quality = params
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                accepted.push((coding, quality));
+            }
+        }
+
+        let quality = |encoding: Encoding| {
+            let find = |name: &str| {
+                accepted
+                    .iter()
+                    .find(|(coding, _)| coding.eq_ignore_ascii_case(name))
+                    .map(|(_, q)| *q)
+            };
+            let named = match encoding {
+                Encoding::Gzip => find("gzip").or_else(|| find("x-gzip")),
+                _ => find(encoding.as_str()),
+            };
+            return named.or_else(|| find("*")).unwrap_or(0.0);
+        };
+
+        let mut best: Option<(Encoding, f32)> = None;
+        for encoding in Encoding::PREFERENCE {
+            if !self.encodings.contains(&encoding) {
+                continue;
+            }
+            let q = quality(encoding);
+            if q > 0.0 && best.map_or(true, |(_, best)| q > best) {
+                best = Some((encoding, q));
+            }
+        }
+        return best.map(|(encoding, _)| encoding);
+    }
+
+    fn should_compress<B>(&self, res: &Response<B>) -> bool {
+        let headers = res.headers();
+        if res.status() == StatusCode::NO_CONTENT
+            || res.status() == StatusCode::NOT_MODIFIED
+            || headers.contains_key(header::CONTENT_ENCODING)
+            || headers.contains_key(header::CONTENT_RANGE)
+        {
+            return false;
+        }
+        let no_transform = headers
+            .get_all(header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .any(|value| value.to_ascii_lowercase().contains("no-transform"));
+        if no_transform {
+            return false;
+        }
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        return (self.predicate)(content_type);
+    }
+}
+
+/// the default predicate, skips media and archives which are compressed already
+fn is_compressible(content_type: &str) -> bool {
+    let content_type = content_type.to_ascii_lowercase();
+    if content_type.starts_with("image/svg") {
+        return true;
+    }
+    let compressed = [
+        "image/",
+        "audio/",
+        "video/",
+        "font/woff",
+        "application/zip",
+        "application/gzip",
+        "application/zstd",
+        "application/x-7z-compressed",
+        "application/grpc",
+    ];
+    return !compressed
+        .iter()
+        .any(|prefix| content_type.starts_with(prefix));
+}
+
+impl<S> Layer<S> for CompressionLayer {
+    type Service = Compression<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return Compression {
+            inner,
+            config: self.clone(),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct Compression<S> {
+    inner: S,
+    config: CompressionLayer,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Compression<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    ResBody: Body<Data = Bytes> + Send + Sync + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<BoxBody>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx).map_err(Into::into);
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let encoding = if req.method() == Method::HEAD {
+            None
+        } else {
+            self.config.negotiate(req.headers())
+        };
+        let config = self.config.clone();
+        let fut = self.inner.call(req);
+        return Box::pin(async move {
+            let res = fut.await.map_err(Into::into)?;
+            if !config.should_compress(&res) {
+                return Ok(res.map(body::boxed));
+            }
+
+            // the response depends on the `Accept-Encoding` of the request, even if it was not
+            // compressed this time
+            let (mut parts, body) = res.into_parts();
+            parts
+                .headers
+                .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+            let Some(encoding) = encoding else {
+                return Ok(Response::from_parts(parts, body::boxed(body)));
+            };
+            let known_small = body
+                .size_hint()
+                .upper()
+                .map_or(false, |upper| upper < config.min_size as u64);
+            if known_small {
+                return Ok(Response::from_parts(parts, body::boxed(body)));
+            }
+
+            let data = body.collect().await.map_err(Into::into)?.to_bytes();
+            if data.len() < config.min_size {
+                return Ok(Response::from_parts(parts, body::boxed(Full::new(data))));
+            }
+            let compressed = if data.len() < BLOCKING_MIN_SIZE {
+                encoding.compress(&data)?
+            } else {
+                tokio::task::spawn_blocking(move || encoding.compress(&data)).await??
+            };
+
+            parts.headers.remove(header::CONTENT_LENGTH);
+            parts.headers.insert(
+                header::CONTENT_ENCODING,
+                HeaderValue::from_static(encoding.as_str()),
+            );
+            // the compressed body is no longer byte for byte the same representation
+            if let Some(etag) = parts.headers.get(header::ETAG) {
+                if !etag.as_bytes().starts_with(b"W/") {
+                    let mut weak = b"W/".to_vec();
+                    weak.extend_from_slice(etag.as_bytes());
+                    let weak = HeaderValue::from_bytes(&weak)?;
+                    parts.headers.insert(header::ETAG, weak);
+                }
+            }
+            return Ok(Response::from_parts(
+                parts,
+                body::boxed(Full::new(Bytes::from(compressed))),
+            ));
+        });
+    }
+}
+
+/// decompresses request bodies sent with a `Content-Encoding` of gzip, deflate, brotli or zstd,
+/// so extractors like [`Json`](crate::json::Json) see the plain body.
+///
+/// requests with an unknown encoding are answered with `415 Unsupported Media Type`, bodies which
+/// fail to decompress with `400 Bad Request` and bodies over the limit, before or after
+/// decompressing, with `413 Payload Too Large`
+#[derive(Clone, Copy, Debug)]
+pub struct DecompressionLayer {
+    max_size: usize,
+}
+
+impl Default for DecompressionLayer {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl DecompressionLayer {
+    /// decompresses bodies of up to 16 MiB
+    pub fn new() -> Self {
+        return Self {
+            max_size: 16 * 1024 * 1024,
+        };
+    }
+
+    /// the maximum size of a body, both compressed and decompressed, guarding against
+    /// decompression bombs
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        return self;
+    }
+}
+
+impl<S> Layer<S> for DecompressionLayer {
+    type Service = Decompression<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return Decompression {
+            inner,
+            max_size: self.max_size,
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct Decompression<S> {
+    inner: S,
+    max_size: usize,
+}
+
+fn status<B: Default>(status: StatusCode) -> Response<B> {
+    let mut res = Response::new(B::default());
+    *res.status_mut() = status;
+    return res;
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Decompression<S>
+where
+    S: Service<Request<BoxBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Body<Data = Bytes> + Send + Sync + 'static,
+    ReqBody::Error: Into<BoxError>,
+    ResBody: Default + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let Some(value) = req.headers().get(header::CONTENT_ENCODING) else {
+            return Box::pin(self.inner.call(req.map(body::boxed)));
+        };
+        if value.as_bytes().eq_ignore_ascii_case(b"identity") {
+            return Box::pin(self.inner.call(req.map(body::boxed)));
+        }
+        let Some(encoding) = Encoding::from_content_encoding(value) else {
+            let res = status(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+            return Box::pin(async move { Ok(res) });
+        };
+
+        // the ready service has to handle the request, the clone waits for the next one
+        let clone = self.inner.clone();
+        let mut inner = mem::replace(&mut self.inner, clone);
+        let max_size = self.max_size;
+        return Box::pin(async move {
+            let (mut parts, body) = req.into_parts();
+            let data = match Limited::new(body, max_size).collect().await {
+                Ok(data) => data.to_bytes(),
+                Err(err) if err.is::<LengthLimitError>() => {
+                    return Ok(status(StatusCode::PAYLOAD_TOO_LARGE));
+                }
+                Err(_) => return Ok(status(StatusCode::BAD_REQUEST)),
+            };
+
+            // how long this takes depends on the decompressed size, which isn't known up front,
+            // so it always runs on the blocking thread pool
+            let decode = move || {
+                let mut decoded = Vec::new();
+                encoding
+                    .decoder(&data)?
+                    .take(max_size as u64 + 1)
+                    .read_to_end(&mut decoded)?;
+                return io::Result::Ok(decoded);
+            };
+            let Ok(Ok(decoded)) = tokio::task::spawn_blocking(decode).await else {
+                return Ok(status(StatusCode::BAD_REQUEST));
+            };
+            if decoded.len() > max_size {
+                return Ok(status(StatusCode::PAYLOAD_TOO_LARGE));
+            }
+
+            parts.headers.remove(header::CONTENT_ENCODING);
+            parts.headers.remove(header::CONTENT_LENGTH);
+            let req = Request::from_parts(parts, body::boxed(Full::new(Bytes::from(decoded))));
+            inner.call(req).await
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tower::{service_fn, ServiceExt};
+
+    fn negotiate(layer: &CompressionLayer, accept_encoding: &str) -> Option<Encoding> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            HeaderValue::from_str(accept_encoding).unwrap(),
+        );
+        return layer.negotiate(&headers);
+    }
+
+    #[test]
+    fn negotiation() {
+        let layer = CompressionLayer::new();
+        assert_eq!(negotiate(&layer, "gzip, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate(&layer, "gzip, deflate"), Some(Encoding::Gzip));
+        assert_eq!(
+            negotiate(&layer, "br;q=0.5, gzip;q=0.8, zstd;q=0.2"),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(negotiate(&layer, "br;q=0, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate(&layer, "X-GZIP"), Some(Encoding::Gzip));
+        assert_eq!(negotiate(&layer, "*"), Some(Encoding::Brotli));
+        assert_eq!(negotiate(&layer, "*, br;q=0"), Some(Encoding::Zstd));
+        assert_eq!(
+            negotiate(&layer, "*;q=0.5, deflate"),
+            Some(Encoding::Deflate)
+        );
+        assert_eq!(negotiate(&layer, "*;q=0"), None);
+        assert_eq!(negotiate(&layer, "identity"), None);
+        assert_eq!(negotiate(&layer, ""), None);
+        assert_eq!(
+            negotiate(&layer.br(false), "br, gzip"),
+            Some(Encoding::Gzip)
+        );
+    }
+
+    async fn compress(
+        layer: CompressionLayer,
+        req: Request<String>,
+        res: Response<String>,
+    ) -> (hyper::http::response::Parts, Bytes) {
+        let service = service_fn(move |_: Request<String>| {
+            let res = res.clone();
+            async move { Ok::<_, Infallible>(res) }
+        });
+        let res = layer.layer(service).oneshot(req).await.unwrap();
+        let (parts, body) = res.into_parts();
+        return (parts, body.collect().await.unwrap().to_bytes());
+    }
+
+    fn accepting(encoding: &str) -> Request<String> {
+        return Request::builder()
+            .header(header::ACCEPT_ENCODING, encoding)
+            .body(String::new())
+            .unwrap();
+    }
+
+    fn decode(encoding: Encoding, data: &[u8]) -> Vec<u8> {
+        let mut decoded = Vec::new();
+        encoding
+            .decoder(data)
+            .unwrap()
+            .read_to_end(&mut decoded)
+            .unwrap();
+        return decoded;
+    }
+
+    #[tokio::test]
+    async fn compression_round_trip() {
+        // large enough to be compressed on the blocking thread pool too
+        for text in ["hello world ".repeat(100), "hello world ".repeat(10_000)] {
+            for encoding in Encoding::PREFERENCE {
+                let res = Response::new(text.clone());
+                let req = accepting(encoding.as_str());
+                let (parts, body) = compress(CompressionLayer::new(), req, res).await;
+                assert_eq!(parts.headers[header::CONTENT_ENCODING], encoding.as_str());
+                assert_eq!(parts.headers[header::VARY], "accept-encoding");
+                assert!(body.len() < text.len());
+                assert_eq!(decode(encoding, &body), text.as_bytes());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn compression_skipped() {
+        let text = "hello world ".repeat(100);
+
+        // too small, but the response still varies by the accepted encodings
+        let res = Response::new("hello".to_string());
+        let (parts, body) = compress(CompressionLayer::new(), accepting("gzip"), res).await;
+        assert!(!parts.headers.contains_key(header::CONTENT_ENCODING));
+        assert_eq!(parts.headers[header::VARY], "accept-encoding");
+        assert_eq!(body, "hello");
+
+        let res = Response::new(text.clone());
+        let layer = CompressionLayer::new().min_size(text.len() + 1);
+        let (parts, _) = compress(layer, accepting("gzip"), res).await;
+        assert!(!parts.headers.contains_key(header::CONTENT_ENCODING));
+
+        let res = Response::builder()
+            .header(header::CONTENT_TYPE, "image/png")
+            .body(text.clone())
+            .unwrap();
+        let (parts, body) = compress(CompressionLayer::new(), accepting("gzip"), res).await;
+        assert!(!parts.headers.contains_key(header::CONTENT_ENCODING));
+        assert!(!parts.headers.contains_key(header::VARY));
+        assert_eq!(body, text);
+
+        let res = Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(text.clone())
+            .unwrap();
+        let layer = CompressionLayer::new().compress_when(|content_type| content_type.is_empty());
+        let (parts, _) = compress(layer, accepting("gzip"), res).await;
+        assert!(!parts.headers.contains_key(header::CONTENT_ENCODING));
+
+        let (parts, body) = compress(
+            CompressionLayer::new(),
+            accepting("identity"),
+            Response::new(text.clone()),
+        )
+        .await;
+        assert!(!parts.headers.contains_key(header::CONTENT_ENCODING));
+        assert_eq!(body, text);
+    }
+
+    async fn decompress(
+        layer: DecompressionLayer,
+        encoding: &str,
+        body: Vec<u8>,
+    ) -> Response<String> {
+        let service = service_fn(|req: Request<BoxBody>| async move {
+            // decoded bodies lose their encoding, identity is passed on as it is
+            let encoding = req.headers().get(header::CONTENT_ENCODING);
+            assert!(encoding.map_or(true, |encoding| encoding == "identity"));
+            let body = req.into_body().collect().await.unwrap().to_bytes();
+            return Ok::<_, Infallible>(Response::new(String::from_utf8(body.to_vec()).unwrap()));
+        });
+        let req = Request::builder()
+            .method(Method::POST)
+            .header(header::CONTENT_ENCODING, encoding)
+            .body(Full::new(Bytes::from(body)))
+            .unwrap();
+        return layer.layer(service).oneshot(req).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn decompression_round_trip() {
+        let text = "hello world ".repeat(1000);
+        for encoding in Encoding::PREFERENCE {
+            let body = encoding.compress(text.as_bytes()).unwrap();
+            let res = decompress(DecompressionLayer::new(), encoding.as_str(), body).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.body(), &text);
+        }
+
+        let body = Encoding::Gzip.compress(text.as_bytes()).unwrap();
+        let res = decompress(DecompressionLayer::new(), "x-gzip", body).await;
+        assert_eq!(res.body(), &text);
+        let res = decompress(DecompressionLayer::new(), "identity", text.clone().into()).await;
+        assert_eq!(res.body(), &text);
+    }
+
+    #[tokio::test]
+    async fn decompression_rejected() {
+        let res = decompress(DecompressionLayer::new(), "compress", b"hello".to_vec()).await;
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        for encoding in Encoding::PREFERENCE {
+            let res = decompress(
+                DecompressionLayer::new(),
+                encoding.as_str(),
+                b"not compressed".to_vec(),
+            )
+            .await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{:?}", encoding);
+        }
+
+        // a small body which decompresses to more than the limit
+        let layer = DecompressionLayer::new().max_size(10_000);
+        for encoding in Encoding::PREFERENCE {
+            let body = encoding.compress(&[0; 100_000]).unwrap();
+            assert!(body.len() < 10_000);
+            let res = decompress(layer, encoding.as_str(), body).await;
+            assert_eq!(
+                res.status(),
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "{:?}",
+                encoding
+            );
+        }
+
+        // the compressed body alone is over the limit
+        let body = Encoding::Gzip.compress(&[0; 100_000]).unwrap();
+        let layer = DecompressionLayer::new().max_size(body.len() - 1);
+        let res = decompress(layer, "gzip", body).await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use hyper::{header, Request, StatusCode};
+use tokio::sync::Semaphore;
+use tower::{Layer, Service};
+
+use crate::http::response::Response;
+
+/// counters of a [`ConcurrencyLimitLayer`], which can be exported with
+/// [`Metrics::track_concurrency`](crate::middleware::metrics::Metrics::track_concurrency)
+#[derive(Debug)]
+pub struct ConcurrencyStats {
+    limit: usize,
+    in_flight: AtomicU64,
+    queued: AtomicU64,
+    shed: AtomicU64,
+}
+
+impl ConcurrencyStats {
+    /// how many requests may be handled at once
+    pub fn limit(&self) -> usize {
+        return self.limit;
+    }
+
+    /// requests currently being handled
+    pub fn in_flight(&self) -> u64 {
+        return self.in_flight.load(Ordering::Relaxed);
+    }
+
+    /// requests currently waiting for another request to finish
+    pub fn queued(&self) -> u64 {
+        return self.queued.load(Ordering::Relaxed);
+    }
+
+    /// requests rejected since the server started, only counted when shedding load
+    pub fn shed(&self) -> u64 {
+        return self.shed.load(Ordering::Relaxed);
+    }
+}
+
+/// decrements a counter of [`ConcurrencyStats`] when dropped, so cancelled requests are counted
+/// correctly
+struct Guard<'a>(&'a AtomicU64);
+
+impl<'a> Guard<'a> {
+    fn new(counter: &'a AtomicU64) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        return Self(counter);
+    }
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// limits how many requests the inner service handles at once, further requests wait until one
+/// of them finished or, with [`ConcurrencyLimitLayer::load_shed`], are answered with
+/// `503 Service Unavailable` right away.
+///
+/// the limit is shared by every service created from the layer and its clones, so one layer can
+/// limit several routes together. routes can get their own limit with
+/// [`MethodRouter::concurrency_limit`](crate::router::method_router::MethodRouter::concurrency_limit)
+///
+/// ```ignore
+/// let reports = ConcurrencyLimitLayer::new(4).load_shed();
+/// metrics.track_concurrency("/reports", reports.stats());
+/// let router = Router::new()
+///     .route("/", get(hello))
+///     .route("/reports", get(report).layer(reports));
+/// ```
+#[derive(Clone, Debug)]
+pub struct ConcurrencyLimitLayer {
+    semaphore: Arc<Semaphore>,
+    stats: Arc<ConcurrencyStats>,
+    load_shed: bool,
+}
+
+impl ConcurrencyLimitLayer {
+    /// allows `max` requests at once, the others wait
+    ///
+    /// # Panics
+    /// panics if `max` is 0
+    pub fn new(max: usize) -> Self {
+        assert!(
+            max > 0,
+            "a concurrency limit has to allow at least one request"
+        );
+        return Self {
+            semaphore: Arc::new(Semaphore::new(max)),
+            stats: Arc::new(ConcurrencyStats {
+                limit: max,
+                in_flight: AtomicU64::new(0),
+                queued: AtomicU64::new(0),
+                shed: AtomicU64::new(0),
+            }),
+            load_shed: false,
+        };
+    }
+
+    /// responds with `503 Service Unavailable` instead of waiting when the limit is reached
+    pub fn load_shed(mut self) -> Self {
+        self.load_shed = true;
+        return self;
+    }
+
+    pub fn stats(&self) -> Arc<ConcurrencyStats> {
+        return self.stats.clone();
+    }
+}
+
+impl<S> Layer<S> for ConcurrencyLimitLayer {
+    type Service = ConcurrencyLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return ConcurrencyLimit {
+            inner,
+            config: self.clone(),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct ConcurrencyLimit<S> {
+    inner: S,
+    config: ConcurrencyLimitLayer,
+}
+
+impl<S, B> Service<Request<B>> for ConcurrencyLimit<S>
+where
+    S: Service<Request<B>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        // the ready service has to handle the request, the clone waits for the next one
+        let clone = self.inner.clone();
+        let mut inner = mem::replace(&mut self.inner, clone);
+        let config = self.config.clone();
+        return Box::pin(async move {
+            let stats = &config.stats;
+            let _permit = match config.semaphore.clone().try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) if config.load_shed => {
+                    stats.shed.fetch_add(1, Ordering::Relaxed);
+                    tracing::debug!(limit = stats.limit, "shed request");
+                    let res = Response::builder()
+                        .status(StatusCode::SERVICE_UNAVAILABLE)
+                        .header(header::RETRY_AFTER, 1)
+                        .body("service overloaded".to_string())
+                        .unwrap();
+                    return Ok(res);
+                }
+                Err(_) => {
+                    let _queued = Guard::new(&stats.queued);
+                    config
+                        .semaphore
+                        .clone()
+                        .acquire_owned()
+                        .await
+                        .expect("the semaphore is never closed")
+                }
+            };
+
+            let _in_flight = Guard::new(&stats.in_flight);
+            return inner.call(req).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tokio::sync::Notify;
+    use tower::{service_fn, ServiceExt};
+
+    /// handles the request once the [`Notify`] in its body is notified
+    async fn wait(req: Request<Arc<Notify>>) -> Result<Response, Infallible> {
+        req.body().notified().await;
+        return Ok(Response::new("hello".to_string()));
+    }
+
+    async fn call(layer: &ConcurrencyLimitLayer, release: &Arc<Notify>) -> Response {
+        let req = Request::new(release.clone());
+        return layer.layer(service_fn(wait)).oneshot(req).await.unwrap();
+    }
+
+    async fn wait_for(counter: impl Fn() -> u64) {
+        while counter() == 0 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn shed() {
+        let layer = ConcurrencyLimitLayer::new(1).load_shed();
+        let stats = layer.stats();
+        let release = Arc::new(Notify::new());
+        let first = tokio::spawn({
+            let (layer, release) = (layer.clone(), release.clone());
+            async move { call(&layer, &release).await }
+        });
+        wait_for(|| stats.in_flight()).await;
+
+        let res = call(&layer, &release).await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "1");
+        assert_eq!(stats.shed(), 1);
+
+        release.notify_one();
+        assert_eq!(first.await.unwrap().status(), StatusCode::OK);
+        assert_eq!(stats.in_flight(), 0);
+
+        release.notify_one();
+        assert_eq!(call(&layer, &release).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn queued() {
+        let layer = ConcurrencyLimitLayer::new(1);
+        let stats = layer.stats();
+        let release = Arc::new(Notify::new());
+        let spawn = || {
+            let (layer, release) = (layer.clone(), release.clone());
+            tokio::spawn(async move { call(&layer, &release).await })
+        };
+        let first = spawn();
+        wait_for(|| stats.in_flight()).await;
+        let second = spawn();
+        wait_for(|| stats.queued()).await;
+        assert_eq!(stats.in_flight(), 1);
+
+        release.notify_one();
+        assert_eq!(first.await.unwrap().status(), StatusCode::OK);
+        wait_for(|| stats.in_flight()).await;
+        assert_eq!(stats.queued(), 0);
+
+        release.notify_one();
+        assert_eq!(second.await.unwrap().status(), StatusCode::OK);
+        assert_eq!(stats.shed(), 0);
+    }
+}
@@ -0,0 +1,404 @@
+use std::{fmt, future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use hyper::{
+    header::{self, HeaderName, HeaderValue},
+    http::request::Parts,
+    Method, Request, Response, StatusCode,
+};
+use tower::{Layer, Service};
+
+type OriginPredicate = Arc<dyn Fn(&HeaderValue, &Parts) -> bool + Send + Sync>;
+
+/// the origins allowed to make cross origin requests
+#[derive(Clone)]
+pub enum AllowOrigin {
+    /// every origin, answered with `*`. can't be combined with credentials
+    Any,
+    /// exactly the listed origins
+    List(Vec<HeaderValue>),
+    /// every origin the function returns true for
+    Predicate(OriginPredicate),
+}
+
+impl AllowOrigin {
+    fn is_allowed(&self, origin: &HeaderValue, parts: &Parts) -> bool {
+        return match self {
+            AllowOrigin::Any => true,
+            AllowOrigin::List(origins) => origins.contains(origin),
+            AllowOrigin::Predicate(f) => f(origin, parts),
+        };
+    }
+}
+
+impl fmt::Debug for AllowOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AllowOrigin::Any => f.write_str("Any"),
+            AllowOrigin::List(origins) => f.debug_tuple("List").field(origins).finish(),
+            AllowOrigin::Predicate(_) => f.write_str("Predicate(..)"),
+        }
+    }
+}
+
+/// adds the cors headers to responses and answers preflight requests itself, before they
+/// reach the router.
+///
+/// nothing is allowed by default
+///
+/// # Panics
+/// creating the service panics if any origin is allowed together with credentials, as that
+/// would let every website make requests with the cookies of the user and read the responses
+///
+/// ```ignore
+/// let cors = CorsLayer::new()
+///     .allow_origins(["https://example.com"])
+///     .allow_methods([Method::GET, Method::POST])
+///     .allow_headers([header::CONTENT_TYPE])
+///     .max_age(Duration::from_secs(600));
+/// ```
+#[derive(Clone, Debug)]
+pub struct CorsLayer {
+    origin: AllowOrigin,
+    methods: Vec<Method>,
+    headers: Vec<HeaderName>,
+    any_header: bool,
+    credentials: bool,
+    expose_headers: Vec<HeaderName>,
+    max_age: Option<Duration>,
+}
+
+impl Default for CorsLayer {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl CorsLayer {
+    pub fn new() -> Self {
+        return Self {
+            origin: AllowOrigin::List(Vec::new()),
+            methods: Vec::new(),
+            headers: Vec::new(),
+            any_header: false,
+            credentials: false,
+            expose_headers: Vec::new(),
+            max_age: None,
+        };
+    }
+
+    /// allows every origin, method and header, without credentials
+    pub fn permissive() -> Self {
+        return Self::new()
+            .allow_any_origin()
+            .allow_methods([
+                Method::GET,
+                Method::HEAD,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+            ])
+            .allow_any_header();
+    }
+
+    pub fn allow_origin(mut self, origin: AllowOrigin) -> Self {
+        self.origin = origin;
+        return self;
+    }
+
+    pub fn allow_any_origin(self) -> Self {
+        return self.allow_origin(AllowOrigin::Any);
+    }
+
+    /// # Panics
+    /// panics if an origin is not a valid header value
+    pub fn allow_origins<I, O>(self, origins: I) -> Self
+    where
+        I: IntoIterator<Item = O>,
+        O: AsRef<str>,
+    {
+        let origins = origins
+            .into_iter()
+            .map(|origin| HeaderValue::from_str(origin.as_ref()).expect("invalid origin"))
+            .collect();
+        return self.allow_origin(AllowOrigin::List(origins));
+    }
+
+    /// allows the origins `f` returns true for, it gets the origin and the request
+    pub fn allow_origin_fn<F>(self, f: F) -> Self
+    where
+        F: Fn(&HeaderValue, &Parts) -> bool + Send + Sync + 'static,
+    {
+        return self.allow_origin(AllowOrigin::Predicate(Arc::new(f)));
+    }
+
+    pub fn allow_methods<I: IntoIterator<Item = Method>>(mut self, methods: I) -> Self {
+        self.methods = methods.into_iter().collect();
+        return self;
+    }
+
+    pub fn allow_headers<I: IntoIterator<Item = HeaderName>>(mut self, headers: I) -> Self {
+        self.headers = headers.into_iter().collect();
+        self.any_header = false;
+        return self;
+    }
+
+    /// allows every header a preflight request asks for
+    pub fn allow_any_header(mut self) -> Self {
+        self.any_header = true;
+        return self;
+    }
+
+    /// allows cookies and authorization headers, which requires the origins to be restricted
+    /// with [`CorsLayer::allow_origins`] or [`CorsLayer::allow_origin_fn`]
+    pub fn allow_credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        return self;
+    }
+
+    pub fn expose_headers<I: IntoIterator<Item = HeaderName>>(mut self, headers: I) -> Self {
+        self.expose_headers = headers.into_iter().collect();
+        return self;
+    }
+
+    /// how long browsers may cache the answer to a preflight request
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        return self;
+    }
+
+    /// the `Access-Control-Allow-Origin` value for the request, `None` if its origin is not
+    /// allowed
+    fn allowed_origin(&self, parts: &Parts) -> Option<HeaderValue> {
+        let origin = parts.headers.get(header::ORIGIN)?;
+        if !self.origin.is_allowed(origin, parts) {
+            return None;
+        }
+        if matches!(self.origin, AllowOrigin::Any) {
+            return Some(HeaderValue::from_static("*"));
+        }
+        return Some(origin.clone());
+    }
+
+    fn preflight<B: Default>(&self, parts: &Parts) -> Response<B> {
+        let mut res = Response::new(B::default());
+        *res.status_mut() = StatusCode::NO_CONTENT;
+        let headers = res.headers_mut();
+        headers.append(
+            header::VARY,
+            HeaderValue::from_static(
+                "origin, access-control-request-method, access-control-request-headers",
+            ),
+        );
+
+        // the browser fails the request when the headers are missing
+        let Some(origin) = self.allowed_origin(parts) else {
+            return res;
+        };
+        let method = parts
+            .headers
+            .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|method| Method::from_bytes(method.as_bytes()).ok());
+        if !method.map_or(false, |method| self.methods.contains(&method)) {
+            return res;
+        }
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        if self.credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if let Some(methods) = join(self.methods.iter().map(Method::as_str)) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, methods);
+        }
+        let allowed_headers = if self.any_header {
+            parts
+                .headers
+                .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+                .cloned()
+        } else {
+            join(self.headers.iter().map(HeaderName::as_str))
+        };
+        if let Some(allowed_headers) = allowed_headers {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allowed_headers);
+        }
+        if let Some(max_age) = self.max_age {
+            headers.insert(header::ACCESS_CONTROL_MAX_AGE, max_age.as_secs().into());
+        }
+        return res;
+    }
+
+    fn add_headers<B>(&self, origin: Option<HeaderValue>, res: &mut Response<B>) {
+        let headers = res.headers_mut();
+        // the response differs between origins, unless every origin gets `*`
+        if !matches!(self.origin, AllowOrigin::Any) {
+            headers.append(header::VARY, HeaderValue::from_static("origin"));
+        }
+
+        let Some(origin) = origin else {
+            return;
+        };
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        if self.credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if let Some(exposed) = join(self.expose_headers.iter().map(HeaderName::as_str)) {
+            headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, exposed);
+        }
+    }
+}
+
+fn join<'a>(values: impl Iterator<Item = &'a str>) -> Option<HeaderValue> {
+    let joined = values.collect::<Vec<_>>().join(", ");
+    if joined.is_empty() {
+        return None;
+    }
+    return HeaderValue::from_str(&joined).ok();
+}
+
+fn is_preflight(parts: &Parts) -> bool {
+    return parts.method == Method::OPTIONS
+        && parts.headers.contains_key(header::ORIGIN)
+        && parts
+            .headers
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+}
+
+impl<S> Layer<S> for CorsLayer {
+    type Service = Cors<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        assert!(
+            !(matches!(self.origin, AllowOrigin::Any) && self.credentials),
+            "CorsLayer can't allow credentials together with any origin, \
+             use `allow_origins` or `allow_origin_fn` instead"
+        );
+        return Cors {
+            inner,
+            config: Arc::new(self.clone()),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct Cors<S> {
+    inner: S,
+    config: Arc<CorsLayer>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Cors<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let (parts, body) = req.into_parts();
+        if is_preflight(&parts) {
+            let res = self.config.preflight(&parts);
+            return Box::pin(async move {
+                return Ok(res);
+            });
+        }
+
+        let origin = self.config.allowed_origin(&parts);
+        let config = self.config.clone();
+        let fut = self.inner.call(Request::from_parts(parts, body));
+        return Box::pin(async move {
+            let mut res = fut.await?;
+            config.add_headers(origin, &mut res);
+            return Ok(res);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tower::{service_fn, ServiceExt};
+
+    async fn call(cors: CorsLayer, req: Request<String>) -> Response<String> {
+        let service = service_fn(|_: Request<String>| async {
+            return Ok::<_, Infallible>(Response::new("hello".to_string()));
+        });
+        return cors.layer(service).oneshot(req).await.unwrap();
+    }
+
+    fn preflight(origin: &str, method: &str) -> Request<String> {
+        return Request::options("/")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, method)
+            .body(String::new())
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn preflight_allowed() {
+        let cors = CorsLayer::new()
+            .allow_origins(["https://example.com"])
+            .allow_methods([Method::GET, Method::PUT])
+            .allow_credentials(true);
+        let res = call(cors, preflight("https://example.com", "PUT")).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let headers = res.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET, PUT");
+    }
+
+    #[tokio::test]
+    async fn preflight_rejected() {
+        let cors = CorsLayer::new()
+            .allow_origins(["https://example.com"])
+            .allow_methods([Method::GET]);
+        let res = call(cors.clone(), preflight("https://example.com", "DELETE")).await;
+        assert!(!res
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        let res = call(cors, preflight("https://evil.example", "GET")).await;
+        assert!(!res
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[tokio::test]
+    async fn any_origin() {
+        let req = Request::get("/")
+            .header(header::ORIGIN, "https://example.com")
+            .body(String::new())
+            .unwrap();
+        let res = call(CorsLayer::permissive(), req).await;
+        assert_eq!(res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(!res.headers().contains_key(header::VARY));
+    }
+
+    #[test]
+    #[should_panic(expected = "can't allow credentials together with any origin")]
+    fn any_origin_with_credentials() {
+        let service = service_fn(|_: Request<String>| async {
+            return Ok::<_, Infallible>(Response::new(String::new()));
+        });
+        let _ = CorsLayer::permissive()
+            .allow_credentials(true)
+            .layer(service);
+    }
+}
@@ -0,0 +1,198 @@
+use std::{future::Future, marker::PhantomData, pin::Pin};
+
+use hyper::StatusCode;
+use tower::{util::BoxCloneService, Layer, Service, ServiceExt};
+
+use crate::http::{
+    body::{BoxBody, BoxError},
+    request::{FromRequestParts, Request, State},
+    response::{IntoResponse, Response},
+};
+
+/// creates middleware from an async function.
+///
+/// the function takes any number of [extractors](FromRequestParts), followed by the request
+/// and the [`Next`] service, and returns anything implementing [`IntoResponse`]
+///
+/// ```ignore
+/// async fn log(method: Method, req: Request<BoxBody>, next: Next) -> Response {
+///     println!("{}", method);
+///     next.run(req).await
+/// }
+///
+/// let router = Router::new().route("/", get(hello)).layer(from_fn(log));
+/// ```
+pub fn from_fn<F, T>(f: F) -> FromFnLayer<F, (), T> {
+    return FromFnLayer {
+        f,
+        state: None,
+        _extractors: PhantomData,
+    };
+}
+
+/// like [`from_fn`], but `state` can be extracted with [`State`] by the function and by
+/// everything behind it
+pub fn from_fn_with_state<F, S, T>(state: S, f: F) -> FromFnLayer<F, S, T> {
+    return FromFnLayer {
+        f,
+        state: Some(state),
+        _extractors: PhantomData,
+    };
+}
+
+pub struct FromFnLayer<F, S, T> {
+    f: F,
+    // `None` for middleware without state, which then isn't inserted into the requests
+    state: Option<S>,
+    _extractors: PhantomData<fn() -> T>,
+}
+
+impl<F, S, T> Clone for FromFnLayer<F, S, T>
+where
+    F: Clone,
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        return Self {
+            f: self.f.clone(),
+            state: self.state.clone(),
+            _extractors: PhantomData,
+        };
+    }
+}
+
+impl<I, F, S, T> Layer<I> for FromFnLayer<F, S, T>
+where
+    F: Clone,
+    S: Clone,
+{
+    type Service = FromFn<F, S, I, T>;
+
+    fn layer(&self, inner: I) -> Self::Service {
+        return FromFn {
+            f: self.f.clone(),
+            state: self.state.clone(),
+            inner,
+            _extractors: PhantomData,
+        };
+    }
+}
+
+pub struct FromFn<F, S, I, T> {
+    f: F,
+    state: Option<S>,
+    inner: I,
+    _extractors: PhantomData<fn() -> T>,
+}
+
+impl<F, S, I, T> Clone for FromFn<F, S, I, T>
+where
+    F: Clone,
+    S: Clone,
+    I: Clone,
+{
+    fn clone(&self) -> Self {
+        return Self {
+            f: self.f.clone(),
+            state: self.state.clone(),
+            inner: self.inner.clone(),
+            _extractors: PhantomData,
+        };
+    }
+}
+
+/// the rest of the middleware stack, including the router
+pub struct Next<B = BoxBody> {
+    inner: BoxCloneService<Request<B>, Response, BoxError>,
+}
+
+impl<B> Next<B> {
+    /// passes the request on, errors of the inner service are logged and become a
+    /// `500 Internal Server Error`
+    pub async fn run(self, req: Request<B>) -> Response {
+        match self.inner.oneshot(req).await {
+            Ok(res) => return res,
+            Err(err) => {
+                tracing::error!(error = %err, "middleware failed");
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body("internal server error".to_string())
+                    .unwrap();
+            }
+        }
+    }
+}
+
+macro_rules! impl_from_fn {
+    (
+        [$($ty:ident),*]
+    ) => {
+        #[allow(non_snake_case, unused_mut)]
+        impl<F, Fut, Out, S, I, B, $($ty,)*> Service<Request<B>> for FromFn<F, S, I, ($($ty,)*)>
+        where
+            F: Fn($($ty,)* Request<B>, Next<B>) -> Fut + Clone + Send + 'static,
+            Fut: Future<Output = Out> + Send,
+            Out: IntoResponse,
+            S: Clone + Send + Sync + 'static,
+            I: Service<Request<B>, Response = Response> + Clone + Send + 'static,
+            I::Future: Send,
+            I::Error: Into<BoxError>,
+            B: Send + 'static,
+            $( $ty: FromRequestParts + Send, )*
+        {
+            type Response = Response;
+            type Error = I::Error;
+            type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+            fn poll_ready(
+                &mut self,
+                cx: &mut std::task::Context<'_>,
+            ) -> std::task::Poll<Result<(), Self::Error>> {
+                self.inner.poll_ready(cx)
+            }
+
+            fn call(&mut self, req: Request<B>) -> Self::Future {
+                let f = self.f.clone();
+                let state = self.state.clone();
+                // the inner service was driven to readiness, so use it and leave a clone behind
+                let clone = self.inner.clone();
+                let inner = std::mem::replace(&mut self.inner, clone);
+                let next = Next {
+                    inner: BoxCloneService::new(inner.map_err(Into::into)),
+                };
+
+                Box::pin(async move {
+                    let (mut parts, body) = req.into_parts();
+                    if let Some(state) = state {
+                        parts.extensions.insert(State(state));
+                    }
+                    $(
+                        let $ty = match $ty::from_request_parts(&parts) {
+                            Ok(value) => value,
+                            Err(err) => {
+                                tracing::debug!(
+                                    error = %err,
+                                    extractor = std::any::type_name::<$ty>(),
+                                    "rejected request"
+                                );
+                                return Ok(err.into_response());
+                            }
+                        };
+                    )*
+                    let req = Request::from_parts(parts, body);
+                    Ok((f)($($ty,)* req, next).await.into_response())
+                })
+            }
+        }
+    };
+}
+
+impl_from_fn!([]);
+impl_from_fn!([T1]);
+impl_from_fn!([T1, T2]);
+impl_from_fn!([T1, T2, T3]);
+impl_from_fn!([T1, T2, T3, T4]);
+impl_from_fn!([T1, T2, T3, T4, T5]);
+impl_from_fn!([T1, T2, T3, T4, T5, T6]);
+impl_from_fn!([T1, T2, T3, T4, T5, T6, T7]);
+impl_from_fn!([T1, T2, T3, T4, T5, T6, T7, T8]);
@@ -0,0 +1,129 @@
+use std::{convert::Infallible, future::Future, pin::Pin};
+
+use hyper::{header, StatusCode};
+use tower::{Layer, Service};
+
+use crate::http::{
+    body::BoxError,
+    response::{IntoResponse, Response},
+};
+
+/// turns errors of the wrapped service into responses using `f`, so clients always get an
+/// http response instead of a dropped connection
+#[derive(Clone)]
+pub struct HandleErrorLayer<F> {
+    f: F,
+}
+
+impl<F> HandleErrorLayer<F> {
+    pub fn new(f: F) -> Self {
+        return Self { f };
+    }
+}
+
+impl<S, F> Layer<S> for HandleErrorLayer<F>
+where
+    F: Clone,
+{
+    type Service = HandleError<S, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return HandleError {
+            inner,
+            f: self.f.clone(),
+            poll_ready_error: None,
+        };
+    }
+}
+
+pub struct HandleError<S, F> {
+    inner: S,
+    f: F,
+    // an error returned by `poll_ready` of the inner service, answered on the next call
+    poll_ready_error: Option<BoxError>,
+}
+
+impl<S, F> Clone for HandleError<S, F>
+where
+    S: Clone,
+    F: Clone,
+{
+    fn clone(&self) -> Self {
+        return Self {
+            inner: self.inner.clone(),
+            f: self.f.clone(),
+            poll_ready_error: None,
+        };
+    }
+}
+
+impl<S, F, R, Req> Service<Req> for HandleError<S, F>
+where
+    S: Service<Req, Response = Response>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    F: Fn(BoxError) -> R + Clone + Send + 'static,
+    R: IntoResponse,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        if self.poll_ready_error.is_some() {
+            return std::task::Poll::Ready(Ok(()));
+        }
+        match self.inner.poll_ready(cx) {
+            std::task::Poll::Ready(Err(err)) => {
+                self.poll_ready_error = Some(err.into());
+                std::task::Poll::Ready(Ok(()))
+            }
+            poll => poll.map(|_| Ok(())),
+        }
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let f = self.f.clone();
+        if let Some(err) = self.poll_ready_error.take() {
+            let res = f(err).into_response();
+            return Box::pin(async move { Ok(res) });
+        }
+
+        let fut = self.inner.call(req);
+        return Box::pin(async move {
+            match fut.await {
+                Ok(res) => Ok(res),
+                Err(err) => Ok(f(err.into()).into_response()),
+            }
+        });
+    }
+}
+
+/// maps the errors of the tower middleware commonly used with axtel to fitting responses:
+///
+/// - a timeout becomes `408 Request Timeout`
+/// - an overloaded service becomes `503 Service Unavailable` with a `Retry-After` header
+/// - everything else is logged and becomes `500 Internal Server Error`
+pub fn default_error_handler(err: BoxError) -> Response {
+    if err.is::<tower::timeout::error::Elapsed>() {
+        return Response::builder()
+            .status(StatusCode::REQUEST_TIMEOUT)
+            .body(err.to_string())
+            .unwrap();
+    }
+    if err.is::<tower::load_shed::error::Overloaded>() {
+        return Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .header(header::RETRY_AFTER, "1")
+            .body(err.to_string())
+            .unwrap();
+    }
+    tracing::error!(error = %err, "unhandled service error");
+    return Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body("internal server error".to_string())
+        .unwrap();
+}
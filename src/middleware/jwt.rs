@@ -0,0 +1,643 @@
+use std::{
+    collections::HashSet,
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    str::FromStr,
+    sync::{Arc, PoisonError, RwLock},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context as _, Result};
+use hyper::{
+    header::{self, HeaderValue},
+    http::request::Parts,
+    Request, StatusCode,
+};
+use jsonwebtoken::{
+    errors::ErrorKind,
+    jwk::{AlgorithmParameters, EllipticCurve, JwkSet, PublicKeyUse},
+    Validation,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::task::JoinHandle;
+use tower::{Layer, Service};
+
+use crate::{
+    http::{
+        request::{FromRequestParts, Rejection},
+        response::{IntoResponse, Response},
+    },
+    middleware::auth::BearerToken,
+};
+
+pub use jsonwebtoken::{Algorithm, DecodingKey};
+
+#[derive(Clone)]
+struct JwtKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    key: DecodingKey,
+}
+
+/// the keys tokens are verified with, tokens with a `kid` are only verified with the key of that
+/// id, others with every key of their algorithm
+#[derive(Clone, Default)]
+pub struct KeySet {
+    keys: Vec<JwtKey>,
+}
+
+impl KeySet {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn add(mut self, kid: Option<&str>, algorithm: Algorithm, key: DecodingKey) -> Self {
+        self.keys.push(JwtKey {
+            kid: kid.map(str::to_string),
+            algorithm,
+            key,
+        });
+        return self;
+    }
+
+    /// a shared secret for HS256
+    pub fn hs256(self, kid: Option<&str>, secret: &[u8]) -> Self {
+        return self.add(kid, Algorithm::HS256, DecodingKey::from_secret(secret));
+    }
+
+    /// a pem encoded rsa public key for RS256
+    pub fn rs256_pem(self, kid: Option<&str>, pem: &[u8]) -> Result<Self> {
+        let key = DecodingKey::from_rsa_pem(pem).context("invalid rsa key")?;
+        return Ok(self.add(kid, Algorithm::RS256, key));
+    }
+
+    /// a pem encoded P-256 public key for ES256
+    pub fn es256_pem(self, kid: Option<&str>, pem: &[u8]) -> Result<Self> {
+        let key = DecodingKey::from_ec_pem(pem).context("invalid ec key")?;
+        return Ok(self.add(kid, Algorithm::ES256, key));
+    }
+
+    /// the signing keys of a json web key set, keys without an `alg` use the usual algorithm of
+    /// their type, e.g. RS256 for rsa keys
+    pub fn from_jwks(jwks: &JwkSet) -> Result<Self> {
+        let mut keys = Self::new();
+        for jwk in &jwks.keys {
+            if jwk.common.public_key_use == Some(PublicKeyUse::Encryption) {
+                continue;
+            }
+            let algorithm = match (&jwk.common.key_algorithm, &jwk.algorithm) {
+                (Some(alg), _) => match Algorithm::from_str(&alg.to_string()) {
+                    Ok(algorithm) => algorithm,
+                    Err(_) => {
+                        let kid = &jwk.common.key_id;
+                        tracing::debug!(?kid, %alg, "skipped key which can't sign");
+                        continue;
+                    }
+                },
+                (None, AlgorithmParameters::RSA(_)) => Algorithm::RS256,
+                (None, AlgorithmParameters::EllipticCurve(params)) => match params.curve {
+                    EllipticCurve::P256 => Algorithm::ES256,
+                    EllipticCurve::P384 => Algorithm::ES384,
+                    _ => continue,
+                },
+                (None, AlgorithmParameters::OctetKey(_)) => Algorithm::HS256,
+                (None, AlgorithmParameters::OctetKeyPair(_)) => Algorithm::EdDSA,
+            };
+            let key = DecodingKey::from_jwk(jwk)
+                .with_context(|| format!("invalid key {:?}", jwk.common.key_id))?;
+            keys = keys.add(jwk.common.key_id.as_deref(), algorithm, key);
+        }
+        return Ok(keys);
+    }
+
+    pub fn from_jwks_json(json: &str) -> Result<Self> {
+        let jwks: JwkSet = serde_json::from_str(json).context("invalid json web key set")?;
+        return Self::from_jwks(&jwks);
+    }
+
+    pub async fn from_jwks_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        return Self::from_jwks_json(&json);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.keys.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.keys.is_empty();
+    }
+
+    fn verify(&self, token: &str, config: &ClaimsConfig) -> Result<Value> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let candidates = self.keys.iter().filter(|key| {
+            // the algorithm is fixed by the key, so tokens can't pick a weaker one
+            key.algorithm == header.alg
+                && (header.kid.is_none() || key.kid.is_none() || key.kid == header.kid)
+        });
+
+        let mut result = Err(anyhow!("no key for the token"));
+        for key in candidates {
+            match jsonwebtoken::decode::<Value>(token, &key.key, &config.validation(key.algorithm))
+            {
+                Ok(data) => return Ok(data.claims),
+                // the signature was valid, so the claims are wrong
+                Err(err) if !is_key_error(err.kind()) => return Err(err.into()),
+                Err(err) => result = Err(err.into()),
+            }
+        }
+        return result;
+    }
+}
+
+fn is_key_error(kind: &ErrorKind) -> bool {
+    return matches!(
+        kind,
+        ErrorKind::InvalidSignature
+            | ErrorKind::InvalidAlgorithm
+            | ErrorKind::InvalidEcdsaKey
+            | ErrorKind::InvalidRsaKey(_)
+            | ErrorKind::InvalidKeyFormat
+    );
+}
+
+#[derive(Clone)]
+struct ClaimsConfig {
+    audience: Vec<String>,
+    issuer: Vec<String>,
+    leeway: Duration,
+}
+
+impl ClaimsConfig {
+    fn validation(&self, algorithm: Algorithm) -> Validation {
+        let mut validation = Validation::new(algorithm);
+        validation.leeway = self.leeway.as_secs();
+        validation.validate_nbf = true;
+        if self.audience.is_empty() {
+            validation.validate_aud = false;
+        } else {
+            validation.set_audience(&self.audience);
+        }
+        if !self.issuer.is_empty() {
+            validation.set_issuer(&self.issuer);
+        }
+        // configured claims have to be present, otherwise tokens could leave them out
+        validation.required_spec_claims = HashSet::from(["exp".to_string()]);
+        if !self.audience.is_empty() {
+            validation.required_spec_claims.insert("aud".to_string());
+        }
+        if !self.issuer.is_empty() {
+            validation.required_spec_claims.insert("iss".to_string());
+        }
+        return validation;
+    }
+}
+
+/// the claims of the token verified by [`JwtLayer`], requests whose claims don't fit `T` are
+/// rejected with `401 Unauthorized`
+///
+/// ```ignore
+/// #[derive(Deserialize)]
+/// struct User {
+///     sub: String,
+///     roles: Vec<String>,
+/// }
+///
+/// async fn me(Claims(user): Claims<User>) -> impl IntoResponse {
+///     user.sub
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Claims<T>(pub T);
+
+/// the raw claims, stored in the request extensions by [`JwtLayer`]
+#[derive(Clone)]
+struct VerifiedClaims(Arc<Value>);
+
+impl<T> FromRequestParts for Claims<T>
+where
+    T: DeserializeOwned,
+{
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        let claims = parts.extensions.get::<VerifiedClaims>().ok_or_else(|| {
+            // the route is missing the layer, which isn't the fault of the client
+            tracing::error!("missing claims, is the JwtLayer applied?");
+            Rejection::new(StatusCode::INTERNAL_SERVER_ERROR, "internal server error")
+        })?;
+        return match T::deserialize(&*claims.0) {
+            Ok(claims) => Ok(Claims(claims)),
+            Err(err) => Err(invalid_token(&format!("invalid claims: {}", err)).into()),
+        };
+    }
+}
+
+fn invalid_token(message: &str) -> Rejection {
+    return Rejection::new(StatusCode::UNAUTHORIZED, message).header(
+        header::WWW_AUTHENTICATE,
+        HeaderValue::from_static("Bearer error=\"invalid_token\""),
+    );
+}
+
+/// only lets requests through with a valid json web token in their `Authorization: Bearer`
+/// header, whose claims can be extracted with [`Claims`].
+///
+/// the signature is checked against a [`KeySet`], and the `exp`, `nbf`, `aud` and `iss` claims
+/// with a leeway of 60 seconds for clock skew. `exp` is required, `aud` and `iss` are only
+/// checked, and then required, if they were configured
+///
+/// ```ignore
+/// let jwt = JwtLayer::from_jwks_file("jwks.json")
+///     .await?
+///     .issuer("https://idp.example.com")
+///     .audience("api");
+/// jwt.reload_every(Duration::from_secs(300));
+///
+/// let router = Router::new().route("/me", get(me)).layer(jwt);
+/// ```
+#[derive(Clone)]
+pub struct JwtLayer {
+    keys: Arc<RwLock<Arc<KeySet>>>,
+    source: Option<Arc<PathBuf>>,
+    config: ClaimsConfig,
+}
+
+impl JwtLayer {
+    pub fn new(keys: KeySet) -> Self {
+        return Self {
+            keys: Arc::new(RwLock::new(Arc::new(keys))),
+            source: None,
+            config: ClaimsConfig {
+                audience: Vec::new(),
+                issuer: Vec::new(),
+                leeway: Duration::from_secs(60),
+            },
+        };
+    }
+
+    /// uses the keys of a json web key set file, which can be read again with
+    /// [`JwtLayer::reload`]
+    pub async fn from_jwks_file(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let mut layer = Self::new(KeySet::from_jwks_file(&path).await?);
+        layer.source = Some(Arc::new(path));
+        return Ok(layer);
+    }
+
+    /// accepts tokens for `audience`, can be called multiple times to accept several
+    pub fn audience(mut self, audience: impl Into<String>) -> Self {
+        self.config.audience.push(audience.into());
+        return self;
+    }
+
+    /// accepts tokens issued by `issuer`, can be called multiple times to accept several
+    pub fn issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.config.issuer.push(issuer.into());
+        return self;
+    }
+
+    /// how far the clocks of the issuer and the server may be apart
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.config.leeway = leeway;
+        return self;
+    }
+
+    /// replaces the keys of this layer and every service created from it
+    pub fn set_keys(&self, keys: KeySet) {
+        *self.keys.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(keys);
+    }
+
+    /// reads the json web key set file again, the old keys are kept if that fails
+    pub async fn reload(&self) -> Result<()> {
+        let path = self
+            .source
+            .as_ref()
+            .ok_or_else(|| anyhow!("the keys were not read from a file"))?;
+        let keys = KeySet::from_jwks_file(path.as_path()).await?;
+        tracing::debug!(path = %path.display(), keys = keys.len(), "reloaded json web keys");
+        self.set_keys(keys);
+        return Ok(());
+    }
+
+    /// spawns a task which reloads the keys every `interval`, until the handle is aborted
+    pub fn reload_every(&self, interval: Duration) -> JoinHandle<()> {
+        let layer = self.clone();
+        return tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            // the first tick completes immediately
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(err) = layer.reload().await {
+                    tracing::warn!(error = %err, "failed to reload json web keys");
+                }
+            }
+        });
+    }
+
+    fn verify(&self, parts: &Parts) -> Result<Value> {
+        let BearerToken(token) = BearerToken::from_request_parts(parts)?;
+        let keys = self
+            .keys
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        return keys.verify(&token, &self.config).map_err(|err| {
+            tracing::debug!(error = %err, "invalid token");
+            invalid_token(&format!("invalid token: {}", err)).into()
+        });
+    }
+}
+
+impl<S> Layer<S> for JwtLayer {
+    type Service = Jwt<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return Jwt {
+            inner,
+            config: self.clone(),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct Jwt<S> {
+    inner: S,
+    config: JwtLayer,
+}
+
+impl<S, B> Service<Request<B>> for Jwt<S>
+where
+    S: Service<Request<B>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let (mut parts, body) = req.into_parts();
+        match self.config.verify(&parts) {
+            Ok(claims) => {
+                parts.extensions.insert(VerifiedClaims(Arc::new(claims)));
+            }
+            Err(err) => {
+                let res = err.into_response();
+                return Box::pin(async move { Ok(res) });
+            }
+        }
+        let fut = self.inner.call(Request::from_parts(parts, body));
+        return Box::pin(fut);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const RSA_KEY: &[u8] = include_bytes!("testdata/jwt_rsa.key");
+    const RSA_PUB: &[u8] = include_bytes!("testdata/jwt_rsa.pub");
+    const EC_KEY: &[u8] = include_bytes!("testdata/jwt_ec.key");
+    const EC_PUB: &[u8] = include_bytes!("testdata/jwt_ec.pub");
+
+    fn now() -> i64 {
+        return SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+    }
+
+    fn claims() -> Value {
+        return json!({ "sub": "alice", "exp": now() + 300 });
+    }
+
+    fn sign(algorithm: Algorithm, kid: Option<&str>, claims: &Value, key: &EncodingKey) -> String {
+        let mut header = Header::new(algorithm);
+        header.kid = kid.map(str::to_string);
+        return jsonwebtoken::encode(&header, claims, key).unwrap();
+    }
+
+    fn hs256(claims: &Value, secret: &[u8]) -> String {
+        return sign(
+            Algorithm::HS256,
+            None,
+            claims,
+            &EncodingKey::from_secret(secret),
+        );
+    }
+
+    fn verify(layer: &JwtLayer, token: &str) -> Result<Value> {
+        let req = Request::builder()
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(())
+            .unwrap();
+        return layer.verify(&req.into_parts().0);
+    }
+
+    fn assert_rejected(res: Result<Value>) {
+        let res = res.unwrap_err().into_response();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert!(res.headers().contains_key(header::WWW_AUTHENTICATE));
+    }
+
+    #[test]
+    fn algorithms() {
+        let keys = KeySet::new()
+            .hs256(None, b"secret")
+            .rs256_pem(None, RSA_PUB)
+            .unwrap()
+            .es256_pem(None, EC_PUB)
+            .unwrap();
+        let layer = JwtLayer::new(keys);
+
+        let token = hs256(&claims(), b"secret");
+        assert_eq!(verify(&layer, &token).unwrap()["sub"], "alice");
+        let key = EncodingKey::from_rsa_pem(RSA_KEY).unwrap();
+        let token = sign(Algorithm::RS256, None, &claims(), &key);
+        assert_eq!(verify(&layer, &token).unwrap()["sub"], "alice");
+        let key = EncodingKey::from_ec_pem(EC_KEY).unwrap();
+        let token = sign(Algorithm::ES256, None, &claims(), &key);
+        assert_eq!(verify(&layer, &token).unwrap()["sub"], "alice");
+    }
+
+    #[test]
+    fn bad_signature() {
+        let layer = JwtLayer::new(KeySet::new().hs256(None, b"secret"));
+        assert_rejected(verify(&layer, &hs256(&claims(), b"other secret")));
+
+        // changed claims don't match the signature anymore
+        let token = hs256(&claims(), b"secret");
+        let mut parts: Vec<_> = token.split('.').map(str::to_string).collect();
+        let forged = json!({ "sub": "admin", "exp": now() + 300 });
+        parts[1] = hs256(&forged, b"secret")
+            .split('.')
+            .nth(1)
+            .unwrap()
+            .to_string();
+        assert_rejected(verify(&layer, &parts.join(".")));
+
+        assert_rejected(verify(&layer, "not a token"));
+    }
+
+    #[test]
+    fn algorithm_confusion() {
+        let layer = JwtLayer::new(KeySet::new().rs256_pem(None, RSA_PUB).unwrap());
+        // the public key is known to everyone, so it must never be used as an hmac secret
+        assert_rejected(verify(&layer, &hs256(&claims(), RSA_PUB)));
+        let key = EncodingKey::from_ec_pem(EC_KEY).unwrap();
+        assert_rejected(verify(
+            &layer,
+            &sign(Algorithm::ES256, None, &claims(), &key),
+        ));
+    }
+
+    #[test]
+    fn expiry() {
+        let layer = JwtLayer::new(KeySet::new().hs256(None, b"secret"));
+        let expired = json!({ "exp": now() - 120 });
+        assert_rejected(verify(&layer, &hs256(&expired, b"secret")));
+        // within the leeway
+        let expired = json!({ "exp": now() - 30 });
+        assert!(verify(&layer, &hs256(&expired, b"secret")).is_ok());
+        let missing = json!({ "sub": "alice" });
+        assert_rejected(verify(&layer, &hs256(&missing, b"secret")));
+    }
+
+    #[test]
+    fn not_before() {
+        let layer = JwtLayer::new(KeySet::new().hs256(None, b"secret"));
+        let within = json!({ "exp": now() + 300, "nbf": now() + 30 });
+        assert!(verify(&layer, &hs256(&within, b"secret")).is_ok());
+        let beyond = json!({ "exp": now() + 300, "nbf": now() + 120 });
+        assert_rejected(verify(&layer, &hs256(&beyond, b"secret")));
+
+        let layer = layer.leeway(Duration::ZERO);
+        assert_rejected(verify(&layer, &hs256(&within, b"secret")));
+    }
+
+    #[test]
+    fn issuer_and_audience() {
+        let layer = JwtLayer::new(KeySet::new().hs256(None, b"secret"))
+            .issuer("https://idp.example.com")
+            .audience("api");
+        let token = |claims: Value| hs256(&claims, b"secret");
+        let (iss, exp) = ("https://idp.example.com", now() + 300);
+
+        assert!(verify(
+            &layer,
+            &token(json!({ "iss": iss, "aud": "api", "exp": exp }))
+        )
+        .is_ok());
+        assert!(verify(
+            &layer,
+            &token(json!({ "iss": iss, "aud": ["web", "api"], "exp": exp }))
+        )
+        .is_ok());
+        assert_rejected(verify(&layer, &token(json!({ "aud": "api", "exp": exp }))));
+        assert_rejected(verify(&layer, &token(json!({ "iss": iss, "exp": exp }))));
+        assert_rejected(verify(
+            &layer,
+            &token(json!({ "iss": "https://evil.example.com", "aud": "api", "exp": exp })),
+        ));
+        assert_rejected(verify(
+            &layer,
+            &token(json!({ "iss": iss, "aud": "web", "exp": exp })),
+        ));
+
+        // unconfigured claims aren't checked
+        let layer = JwtLayer::new(KeySet::new().hs256(None, b"secret"));
+        assert!(verify(
+            &layer,
+            &token(json!({ "iss": "anyone", "aud": "web", "exp": exp }))
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn kid() {
+        let keys = KeySet::new()
+            .hs256(Some("a"), b"secret a")
+            .hs256(Some("b"), b"secret b");
+        let layer = JwtLayer::new(keys);
+        let key_b = EncodingKey::from_secret(b"secret b");
+
+        assert!(verify(
+            &layer,
+            &sign(Algorithm::HS256, Some("b"), &claims(), &key_b)
+        )
+        .is_ok());
+        // without a kid every key of the algorithm is tried
+        assert!(verify(&layer, &sign(Algorithm::HS256, None, &claims(), &key_b)).is_ok());
+        // a kid restricts the token to that key
+        assert_rejected(verify(
+            &layer,
+            &sign(Algorithm::HS256, Some("a"), &claims(), &key_b),
+        ));
+        assert_rejected(verify(
+            &layer,
+            &sign(Algorithm::HS256, Some("c"), &claims(), &key_b),
+        ));
+    }
+
+    #[test]
+    fn missing_claims() {
+        let (parts, _) = Request::new(()).into_parts();
+        let err = Claims::<Value>::from_request_parts(&parts).unwrap_err();
+        let res = err.into_response();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(res.body(), "internal server error");
+    }
+
+    #[tokio::test]
+    async fn set_keys_and_reload() {
+        let layer = JwtLayer::new(KeySet::new().hs256(None, b"old"));
+        // clones, like the services created from the layer, share its keys
+        let service = layer.clone();
+        assert!(verify(&service, &hs256(&claims(), b"old")).is_ok());
+
+        layer.set_keys(KeySet::new().hs256(None, b"new"));
+        assert_rejected(verify(&service, &hs256(&claims(), b"old")));
+        assert!(verify(&service, &hs256(&claims(), b"new")).is_ok());
+
+        assert!(layer.reload().await.is_err());
+
+        let path =
+            std::env::temp_dir().join(format!("jwks-{}.json", uuid::Uuid::new_v4().simple()));
+        // base64url of "first"
+        let jwks =
+            |k: &str| json!({ "keys": [{ "kty": "oct", "kid": "k", "alg": "HS256", "k": k }] });
+        tokio::fs::write(&path, jwks("Zmlyc3Q").to_string())
+            .await
+            .unwrap();
+        let layer = JwtLayer::from_jwks_file(&path).await.unwrap();
+        let service = layer.clone();
+        assert!(verify(&service, &hs256(&claims(), b"first")).is_ok());
+
+        // base64url of "second"
+        tokio::fs::write(&path, jwks("c2Vjb25k").to_string())
+            .await
+            .unwrap();
+        layer.reload().await.unwrap();
+        assert_rejected(verify(&service, &hs256(&claims(), b"first")));
+        assert!(verify(&service, &hs256(&claims(), b"second")).is_ok());
+
+        // the old keys are kept if the file can't be read
+        tokio::fs::write(&path, "{").await.unwrap();
+        assert!(layer.reload().await.is_err());
+        assert!(verify(&service, &hs256(&claims(), b"second")).is_ok());
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
@@ -0,0 +1,553 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    future::{Future, Ready},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use hyper::{header, Method, Request, Response, StatusCode};
+use tower::{Layer, Service};
+
+use crate::{
+    middleware::concurrency_limit::ConcurrencyStats, router::MatchedPath,
+    server::stats::AcceptorStats,
+};
+
+/// the default latency buckets in seconds, the same as the prometheus client libraries use
+const DEFAULT_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// the label of requests which did not match a route, so random paths don't create new series
+const UNMATCHED: &str = "unmatched";
+
+/// the name, type, help and value of a metric exported from [`AcceptorStats`]
+type Family = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&AcceptorStats) -> u64,
+);
+
+/// the name, type, help and value of a metric exported from [`ConcurrencyStats`]
+type ConcurrencyFamily = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&ConcurrencyStats) -> u64,
+);
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct Labels {
+    method: &'static str,
+    route: String,
+    status: &'static str,
+}
+
+struct Series {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+#[derive(Default)]
+struct Registry {
+    buckets: Vec<f64>,
+    requests: Mutex<HashMap<Labels, Series>>,
+    in_flight: Mutex<HashMap<&'static str, i64>>,
+    acceptors: Mutex<Vec<Arc<AcceptorStats>>>,
+    limits: Mutex<Vec<(String, Arc<ConcurrencyStats>)>>,
+}
+
+/// the standard methods label themselves, any other method is counted as `other` so clients
+/// can't create new series at will
+fn method_label(method: &Method) -> &'static str {
+    return match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::CONNECT => "CONNECT",
+        Method::OPTIONS => "OPTIONS",
+        Method::TRACE => "TRACE",
+        Method::PATCH => "PATCH",
+        _ => "other",
+    };
+}
+
+/// request counts, latency histograms and in-flight gauges recorded by [`MetricsLayer`], along
+/// with the connection counts of [`Serve`](crate::server::serve::Serve), rendered in the
+/// prometheus text format.
+///
+/// requests are labelled by method, the [`MatchedPath`] of the route and the status class
+///
+/// ```ignore
+/// let metrics = Metrics::new();
+/// let router = Router::new()
+///     .route("/", get(hello))
+///     .route("/metrics", get(metrics.handler()))
+///     .layer(metrics.layer());
+/// let serve = serve(listener, router);
+/// metrics.track_connections(serve.stats());
+/// serve.await?;
+/// ```
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Arc<Registry>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        return Self::with_buckets(DEFAULT_BUCKETS.to_vec());
+    }
+
+    /// uses `buckets` as the upper bounds in seconds of the latency histogram
+    pub fn with_buckets(mut buckets: Vec<f64>) -> Self {
+        buckets.sort_by(|a, b| a.total_cmp(b));
+        buckets.dedup();
+        return Self {
+            registry: Arc::new(Registry {
+                buckets,
+                ..Default::default()
+            }),
+        };
+    }
+
+    pub fn layer(&self) -> MetricsLayer {
+        return MetricsLayer {
+            metrics: self.clone(),
+        };
+    }
+
+    /// exports the connection counts of the acceptors, see
+    /// [`Serve::stats`](crate::server::serve::Serve::stats)
+    pub fn track_connections<I>(&self, stats: I)
+    where
+        I: IntoIterator<Item = Arc<AcceptorStats>>,
+    {
+        self.registry.acceptors.lock().unwrap().extend(stats);
+    }
+
+    /// exports the in-flight requests, queue depth and shed requests of a
+    /// [`ConcurrencyLimitLayer`](crate::middleware::concurrency_limit::ConcurrencyLimitLayer),
+    /// labelled with `route`
+    pub fn track_concurrency(&self, route: impl Into<String>, stats: Arc<ConcurrencyStats>) {
+        self.registry
+            .limits
+            .lock()
+            .unwrap()
+            .push((route.into(), stats));
+    }
+
+    /// a handler responding with the rendered metrics
+    pub fn handler(
+        &self,
+    ) -> impl Fn() -> Ready<crate::http::response::Response> + Clone + Send + Sync + 'static {
+        let metrics = self.clone();
+        return move || {
+            let res = Response::builder()
+                .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+                .body(metrics.render())
+                .unwrap();
+            std::future::ready(res)
+        };
+    }
+
+    fn start(&self, method: &Method) {
+        let mut in_flight = self.registry.in_flight.lock().unwrap();
+        *in_flight.entry(method_label(method)).or_default() += 1;
+    }
+
+    fn finish(
+        &self,
+        method: &Method,
+        route: Option<&MatchedPath>,
+        status: &'static str,
+        start: Instant,
+    ) {
+        let seconds = start.elapsed().as_secs_f64();
+        {
+            let mut in_flight = self.registry.in_flight.lock().unwrap();
+            *in_flight.entry(method_label(method)).or_default() -= 1;
+        }
+
+        let labels = Labels {
+            method: method_label(method),
+            route: route.map_or(UNMATCHED, MatchedPath::as_str).to_string(),
+            status,
+        };
+        let mut requests = self.registry.requests.lock().unwrap();
+        let series = requests.entry(labels).or_insert_with(|| Series {
+            buckets: vec![0; self.registry.buckets.len()],
+            count: 0,
+            sum: 0.0,
+        });
+        for (count, bound) in series.buckets.iter_mut().zip(&self.registry.buckets) {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+        series.count += 1;
+        series.sum += seconds;
+    }
+
+    /// the metrics in the prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let requests = self.registry.requests.lock().unwrap();
+        let mut series: Vec<_> = requests.iter().collect();
+        series.sort_by(|a, b| a.0.cmp(b.0));
+
+        out.push_str("# HELP axtel_http_requests_total the number of handled requests\n");
+        out.push_str("# TYPE axtel_http_requests_total counter\n");
+        for (labels, series) in &series {
+            let _ = writeln!(
+                out,
+                "axtel_http_requests_total{{{}}} {}",
+                labels.render(),
+                series.count
+            );
+        }
+
+        out.push_str("# HELP axtel_http_request_duration_seconds the latency of requests\n");
+        out.push_str("# TYPE axtel_http_request_duration_seconds histogram\n");
+        for (labels, series) in &series {
+            let labels = labels.render();
+            for (count, bound) in series.buckets.iter().zip(&self.registry.buckets) {
+                let _ = writeln!(
+                    out,
+                    "axtel_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "axtel_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, series.count
+            );
+            let _ = writeln!(
+                out,
+                "axtel_http_request_duration_seconds_sum{{{}}} {}",
+                labels, series.sum
+            );
+            let _ = writeln!(
+                out,
+                "axtel_http_request_duration_seconds_count{{{}}} {}",
+                labels, series.count
+            );
+        }
+        drop(requests);
+
+        let in_flight = self.registry.in_flight.lock().unwrap();
+        let mut methods: Vec<_> = in_flight.iter().collect();
+        methods.sort();
+        out.push_str("# HELP axtel_http_requests_in_flight the number of requests being handled\n");
+        out.push_str("# TYPE axtel_http_requests_in_flight gauge\n");
+        for (method, count) in methods {
+            let _ = writeln!(
+                out,
+                "axtel_http_requests_in_flight{{method=\"{}\"}} {}",
+                escape(method),
+                count
+            );
+        }
+        drop(in_flight);
+
+        let acceptors = self.registry.acceptors.lock().unwrap();
+        if !acceptors.is_empty() {
+            let families: [Family; 3] = [
+                (
+                    "axtel_connections_accepted_total",
+                    "counter",
+                    "the number of accepted connections",
+                    AcceptorStats::accepted,
+                ),
+                (
+                    "axtel_connections_active",
+                    "gauge",
+                    "the number of open connections",
+                    AcceptorStats::active,
+                ),
+                (
+                    "axtel_accept_errors_total",
+                    "counter",
+                    "the number of failed accepts",
+                    AcceptorStats::accept_errors,
+                ),
+            ];
+            for (name, kind, help, value) in families {
+                let _ = writeln!(out, "# HELP {} {}", name, help);
+                let _ = writeln!(out, "# TYPE {} {}", name, kind);
+                for stats in acceptors.iter() {
+                    let listener = match (stats.name(), stats.local_addr()) {
+                        (Some(name), _) => name.to_string(),
+                        (None, Some(addr)) => addr.to_string(),
+                        (None, None) => "unix".to_string(),
+                    };
+                    let _ = writeln!(
+                        out,
+                        "{}{{listener=\"{}\"}} {}",
+                        name,
+                        escape(&listener),
+                        value(stats)
+                    );
+                }
+            }
+        }
+        drop(acceptors);
+
+        let limits = self.registry.limits.lock().unwrap();
+        if !limits.is_empty() {
+            let families: [ConcurrencyFamily; 4] = [
+                (
+                    "axtel_route_concurrency_limit",
+                    "gauge",
+                    "the number of requests a route may handle at once",
+                    |stats| stats.limit() as u64,
+                ),
+                (
+                    "axtel_route_in_flight",
+                    "gauge",
+                    "the number of requests a limited route is handling",
+                    ConcurrencyStats::in_flight,
+                ),
+                (
+                    "axtel_route_queue_depth",
+                    "gauge",
+                    "the number of requests waiting for a limited route",
+                    ConcurrencyStats::queued,
+                ),
+                (
+                    "axtel_route_shed_total",
+                    "counter",
+                    "the number of requests rejected by a saturated route",
+                    ConcurrencyStats::shed,
+                ),
+            ];
+            for (name, kind, help, value) in families {
+                let _ = writeln!(out, "# HELP {} {}", name, help);
+                let _ = writeln!(out, "# TYPE {} {}", name, kind);
+                for (route, stats) in limits.iter() {
+                    let _ = writeln!(
+                        out,
+                        "{}{{route=\"{}\"}} {}",
+                        name,
+                        escape(route),
+                        value(stats)
+                    );
+                }
+            }
+        }
+        return out;
+    }
+}
+
+impl Labels {
+    fn render(&self) -> String {
+        return format!(
+            "method=\"{}\",route=\"{}\",status=\"{}\"",
+            escape(self.method),
+            escape(&self.route),
+            self.status
+        );
+    }
+}
+
+fn escape(value: &str) -> String {
+    return value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+}
+
+fn status_class(status: StatusCode) -> &'static str {
+    return match status.as_u16() {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        _ => "5xx",
+    };
+}
+
+/// records every request in [`Metrics`]
+#[derive(Clone)]
+pub struct MetricsLayer {
+    metrics: Metrics,
+}
+
+impl MetricsLayer {
+    pub fn new(metrics: Metrics) -> Self {
+        return Self { metrics };
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = RecordMetrics<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return RecordMetrics {
+            inner,
+            metrics: self.metrics.clone(),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct RecordMetrics<S> {
+    inner: S,
+    metrics: Metrics,
+}
+
+/// finishes the request if the future is dropped before the response is ready, so the in-flight
+/// gauge doesn't leak
+struct InFlight {
+    metrics: Metrics,
+    method: Method,
+    start: Instant,
+    route: Option<MatchedPath>,
+    status: Option<&'static str>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        // requests dropped before completion are counted as server errors
+        let status = self.status.unwrap_or("5xx");
+        self.metrics
+            .finish(&self.method, self.route.as_ref(), status, self.start);
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RecordMetrics<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        self.metrics.start(req.method());
+        let mut in_flight = InFlight {
+            metrics: self.metrics.clone(),
+            method: req.method().clone(),
+            start: Instant::now(),
+            // set when the layer is added with `route_layer`
+            route: req.extensions().get::<MatchedPath>().cloned(),
+            status: None,
+        };
+
+        let fut = self.inner.call(req);
+        return Box::pin(async move {
+            let res = fut.await;
+            if let Ok(res) = &res {
+                if let Some(route) = res.extensions().get::<MatchedPath>() {
+                    in_flight.route = Some(route.clone());
+                }
+                in_flight.status = Some(status_class(res.status()));
+            }
+            drop(in_flight);
+            res
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tower::{service_fn, ServiceExt};
+
+    async fn record(metrics: &Metrics, method: &str, route: Option<&str>, status: u16) {
+        let service = service_fn(move |_: Request<String>| async move {
+            let res = Response::builder()
+                .status(status)
+                .body(String::new())
+                .unwrap();
+            return Ok::<_, Infallible>(res);
+        });
+        let mut req = Request::builder()
+            .method(method)
+            .body(String::new())
+            .unwrap();
+        if let Some(route) = route {
+            req.extensions_mut().insert(MatchedPath::new(route));
+        }
+        metrics.layer().layer(service).oneshot(req).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn render() {
+        let metrics = Metrics::with_buckets(vec![1.0, 0.1]);
+        record(&metrics, "GET", Some("/users"), 200).await;
+        record(&metrics, "GET", Some("/users"), 204).await;
+        record(&metrics, "POST", Some("/say/\"hi\"\\"), 500).await;
+        record(&metrics, "PURGE", None, 404).await;
+        let stats = AcceptorStats::new(None, Some("main\n".to_string()));
+        stats.connection_opened();
+        metrics.track_connections([Arc::new(stats)]);
+
+        // the sums depend on how long the requests took
+        let rendered: Vec<_> = metrics
+            .render()
+            .lines()
+            .map(|line| match line.rsplit_once(' ') {
+                Some((series, _)) if series.contains("_sum{") => format!("{} <sum>", series),
+                _ => line.to_string(),
+            })
+            .collect();
+        let expected = r#"# HELP axtel_http_requests_total the number of handled requests
+# TYPE axtel_http_requests_total counter
+axtel_http_requests_total{method="GET",route="/users",status="2xx"} 2
+axtel_http_requests_total{method="POST",route="/say/\"hi\"\\",status="5xx"} 1
+axtel_http_requests_total{method="other",route="unmatched",status="4xx"} 1
+# HELP axtel_http_request_duration_seconds the latency of requests
+# TYPE axtel_http_request_duration_seconds histogram
+axtel_http_request_duration_seconds_bucket{method="GET",route="/users",status="2xx",le="0.1"} 2
+axtel_http_request_duration_seconds_bucket{method="GET",route="/users",status="2xx",le="1"} 2
+axtel_http_request_duration_seconds_bucket{method="GET",route="/users",status="2xx",le="+Inf"} 2
+axtel_http_request_duration_seconds_sum{method="GET",route="/users",status="2xx"} <sum>
+axtel_http_request_duration_seconds_count{method="GET",route="/users",status="2xx"} 2
+axtel_http_request_duration_seconds_bucket{method="POST",route="/say/\"hi\"\\",status="5xx",le="0.1"} 1
+axtel_http_request_duration_seconds_bucket{method="POST",route="/say/\"hi\"\\",status="5xx",le="1"} 1
+axtel_http_request_duration_seconds_bucket{method="POST",route="/say/\"hi\"\\",status="5xx",le="+Inf"} 1
+axtel_http_request_duration_seconds_sum{method="POST",route="/say/\"hi\"\\",status="5xx"} <sum>
+axtel_http_request_duration_seconds_count{method="POST",route="/say/\"hi\"\\",status="5xx"} 1
+axtel_http_request_duration_seconds_bucket{method="other",route="unmatched",status="4xx",le="0.1"} 1
+axtel_http_request_duration_seconds_bucket{method="other",route="unmatched",status="4xx",le="1"} 1
+axtel_http_request_duration_seconds_bucket{method="other",route="unmatched",status="4xx",le="+Inf"} 1
+axtel_http_request_duration_seconds_sum{method="other",route="unmatched",status="4xx"} <sum>
+axtel_http_request_duration_seconds_count{method="other",route="unmatched",status="4xx"} 1
+# HELP axtel_http_requests_in_flight the number of requests being handled
+# TYPE axtel_http_requests_in_flight gauge
+axtel_http_requests_in_flight{method="GET"} 0
+axtel_http_requests_in_flight{method="POST"} 0
+axtel_http_requests_in_flight{method="other"} 0
+# HELP axtel_connections_accepted_total the number of accepted connections
+# TYPE axtel_connections_accepted_total counter
+axtel_connections_accepted_total{listener="main\n"} 1
+# HELP axtel_connections_active the number of open connections
+# TYPE axtel_connections_active gauge
+axtel_connections_active{listener="main\n"} 1
+# HELP axtel_accept_errors_total the number of failed accepts
+# TYPE axtel_accept_errors_total counter
+axtel_accept_errors_total{listener="main\n"} 0"#;
+        assert_eq!(rendered.join("\n"), expected);
+    }
+}
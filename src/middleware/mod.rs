@@ -0,0 +1,73 @@
+pub mod access_log;
+pub mod auth;
+pub mod catch_panic;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod concurrency_limit;
+pub mod cors;
+pub mod from_fn;
+pub mod handle_error;
+#[cfg(feature = "jwt")]
+pub mod jwt;
+pub mod metrics;
+pub mod rate_limit;
+pub mod request_id;
+pub mod security_headers;
+pub mod session;
+pub mod state;
+pub mod timeout;
+pub mod trace;
+
+use tower::{
+    layer::util::{Identity, Stack},
+    Layer,
+};
+
+use crate::router::{method_router::MethodRouter, Router};
+
+use self::handle_error::HandleErrorLayer;
+
+pub struct Middleware<L> {
+    pub(crate) layer: L,
+    pub(crate) router: Router,
+}
+
+impl Middleware<Identity> {
+    pub fn new(router: Router) -> Self {
+        Self {
+            layer: Identity::new(),
+            router,
+        }
+    }
+}
+
+impl<L> Middleware<L> {
+    pub fn layer<T>(self, layer: T) -> Middleware<Stack<T, L>> {
+        Middleware {
+            layer: Stack::new(layer, self.layer),
+            router: self.router,
+        }
+    }
+
+    /// turns errors of the router and of every layer added so far into responses, see
+    /// [`HandleErrorLayer`]
+    pub fn handle_error<F>(self, f: F) -> Middleware<Stack<L, HandleErrorLayer<F>>> {
+        Middleware {
+            layer: Stack::new(self.layer, HandleErrorLayer::new(f)),
+            router: self.router,
+        }
+    }
+
+    ///adds a route to the router see ['Router::route'](Router) for more info
+    pub fn route(mut self, path: &str, route: impl Into<MethodRouter>) -> Self {
+        self.router = self.router.route(path, route);
+        return self;
+    }
+
+    pub fn service(self) -> L::Service
+    where
+        L: Layer<Router>,
+    {
+        self.layer.layer(self.router)
+    }
+}
@@ -0,0 +1,424 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use hyper::{
+    header::{self, HeaderName, HeaderValue},
+    http::request::Parts,
+    Request, StatusCode,
+};
+use tower::{Layer, Service};
+
+use crate::{
+    http::{request::FromRequestParts, response::Response},
+    server::connect_info::ConnectInfo,
+};
+
+/// how many requests a client may make, as a sustained rate and a burst on top of it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quota {
+    /// the time it takes to replenish one request
+    interval: Duration,
+    burst: u32,
+}
+
+impl Quota {
+    /// `requests` per `period`, which can all be made at once
+    ///
+    /// # Panics
+    /// panics if `requests` is 0
+    pub fn new(requests: u32, period: Duration) -> Self {
+        assert!(requests > 0, "a quota has to allow at least one request");
+        return Self {
+            interval: period / requests,
+            burst: requests,
+        };
+    }
+
+    pub fn per_second(requests: u32) -> Self {
+        return Self::new(requests, Duration::from_secs(1));
+    }
+
+    pub fn per_minute(requests: u32) -> Self {
+        return Self::new(requests, Duration::from_secs(60));
+    }
+
+    /// how many requests can be made at once, the rate stays the same
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        return self;
+    }
+
+    /// decides whether a request at `now` is allowed, using the generic cell rate algorithm.
+    ///
+    /// `tat` is the theoretical arrival time stored for the key, if any, and both times are
+    /// measured from the same arbitrary epoch. returns the decision and the new time to store
+    pub fn check(&self, tat: Option<Duration>, now: Duration) -> (Decision, Duration) {
+        let tolerance = self.interval * self.burst;
+        let tat = tat.unwrap_or(now).max(now);
+        let new_tat = tat + self.interval;
+
+        // the request fits if the bucket it would fill doesn't reach past the tolerance
+        let allow_at = new_tat.saturating_sub(tolerance);
+        if now < allow_at {
+            let decision = Decision {
+                allowed: false,
+                limit: self.burst,
+                remaining: 0,
+                reset: tat - now,
+                retry_after: Some(allow_at - now),
+            };
+            return (decision, tat);
+        }
+
+        let used = new_tat - now;
+        let remaining =
+            (tolerance.saturating_sub(used).as_nanos() / self.interval.as_nanos().max(1)) as u32;
+        let decision = Decision {
+            allowed: true,
+            limit: self.burst,
+            remaining,
+            reset: used,
+            retry_after: None,
+        };
+        return (decision, new_tat);
+    }
+}
+
+/// the result of checking a request against its [`Quota`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Decision {
+    pub allowed: bool,
+    /// the burst of the quota
+    pub limit: u32,
+    /// how many more requests can be made right now
+    pub remaining: u32,
+    /// when the full burst is available again
+    pub reset: Duration,
+    /// when the next request will be allowed, if this one was rejected
+    pub retry_after: Option<Duration>,
+}
+
+type StoreFuture<'a> = Pin<Box<dyn Future<Output = Result<Decision>> + Send + 'a>>;
+
+/// where the state of the rate limiter is kept, implementations only have to atomically store
+/// the time returned by [`Quota::check`] per key, e.g. in redis to share limits between servers
+pub trait RateLimitStore: Send + Sync + 'static {
+    fn check<'a>(&'a self, key: &'a str, quota: &'a Quota) -> StoreFuture<'a>;
+}
+
+/// keeps the state in memory, keys which are back to their full burst are evicted periodically
+pub struct MemoryStore {
+    epoch: Instant,
+    sweep_interval: Duration,
+    state: Mutex<MemoryState>,
+}
+
+struct MemoryState {
+    tats: HashMap<String, Duration>,
+    last_sweep: Duration,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        return Self {
+            epoch: Instant::now(),
+            sweep_interval: Duration::from_secs(60),
+            state: Mutex::new(MemoryState {
+                tats: HashMap::new(),
+                last_sweep: Duration::ZERO,
+            }),
+        };
+    }
+
+    /// how often keys which are back to their full burst are removed
+    pub fn sweep_interval(mut self, interval: Duration) -> Self {
+        self.sweep_interval = interval;
+        return self;
+    }
+
+    pub fn len(&self) -> usize {
+        return self
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .tats
+            .len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn check<'a>(&'a self, key: &'a str, quota: &'a Quota) -> StoreFuture<'a> {
+        let now = self.epoch.elapsed();
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if now.saturating_sub(state.last_sweep) >= self.sweep_interval {
+            // a time in the past means the key has its full burst again, which is the same as
+            // not being stored at all
+            state.tats.retain(|_, tat| *tat > now);
+            state.last_sweep = now;
+        }
+
+        let (decision, tat) = quota.check(state.tats.get(key).copied(), now);
+        if decision.allowed {
+            state.tats.insert(key.to_string(), tat);
+        }
+        return Box::pin(std::future::ready(Ok(decision)));
+    }
+}
+
+type KeyFn = Arc<dyn Fn(&Parts) -> Option<String> + Send + Sync>;
+
+/// limits the rate of requests per client, answering requests over the [`Quota`] with
+/// `429 Too Many Requests`.
+///
+/// clients are told about their limit with the `RateLimit-Limit`, `RateLimit-Remaining` and
+/// `RateLimit-Reset` headers, and rejected requests get a `Retry-After`. clients are told apart
+/// by their ip by default, requests without a key share one limit
+///
+/// ```ignore
+/// let router = Router::new()
+///     .route("/", get(hello))
+///     .layer(RateLimitLayer::new(Quota::per_minute(60)).key_by_header("x-api-key"));
+/// ```
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    quota: Quota,
+    key: KeyFn,
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimitLayer {
+    /// limits every peer ip to `quota`, with the state kept in a [`MemoryStore`]
+    pub fn new(quota: Quota) -> Self {
+        return Self {
+            quota,
+            key: Arc::new(|parts: &Parts| {
+                let info = parts.extensions.get::<ConnectInfo>()?;
+                Some(info.remote_addr.ip().to_string())
+            }),
+            store: Arc::new(MemoryStore::new()),
+        };
+    }
+
+    pub fn store<S: RateLimitStore>(mut self, store: S) -> Self {
+        self.store = Arc::new(store);
+        return self;
+    }
+
+    /// uses the key `f` returns for the request
+    pub fn key_by<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Parts) -> Option<String> + Send + Sync + 'static,
+    {
+        self.key = Arc::new(f);
+        return self;
+    }
+
+    /// uses the value of `header` as the key, e.g. an api key
+    ///
+    /// # Panics
+    /// panics if `header` is not a valid header name
+    pub fn key_by_header(self, header: impl AsRef<str>) -> Self {
+        let header = HeaderName::try_from(header.as_ref()).expect("invalid header name");
+        return self.key_by(move |parts| {
+            let value = parts.headers.get(&header)?;
+            Some(String::from_utf8_lossy(value.as_bytes()).into_owned())
+        });
+    }
+
+    /// uses the key `f` returns for the extractor `T`, requests it rejects have no key
+    pub fn key_by_extractor<T, F>(self, f: F) -> Self
+    where
+        T: FromRequestParts,
+        F: Fn(T) -> String + Send + Sync + 'static,
+    {
+        return self.key_by(move |parts| T::from_request_parts(parts).ok().map(&f));
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return RateLimit {
+            inner,
+            config: self.clone(),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimit<S> {
+    inner: S,
+    config: RateLimitLayer,
+}
+
+fn seconds(duration: Duration) -> HeaderValue {
+    let seconds = duration.as_secs() + u64::from(duration.subsec_nanos() > 0);
+    return HeaderValue::from(seconds);
+}
+
+fn insert_headers(res: &mut Response, decision: &Decision) {
+    let headers = res.headers_mut();
+    headers.insert(
+        HeaderName::from_static("ratelimit-limit"),
+        HeaderValue::from(decision.limit),
+    );
+    headers.insert(
+        HeaderName::from_static("ratelimit-remaining"),
+        HeaderValue::from(decision.remaining),
+    );
+    headers.insert(
+        HeaderName::from_static("ratelimit-reset"),
+        seconds(decision.reset),
+    );
+    if let Some(retry_after) = decision.retry_after {
+        headers.insert(header::RETRY_AFTER, seconds(retry_after));
+    }
+}
+
+impl<S, B> Service<Request<B>> for RateLimit<S>
+where
+    S: Service<Request<B>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        // the ready service has to handle the request, the clone waits for the next one
+        let clone = self.inner.clone();
+        let mut inner = mem::replace(&mut self.inner, clone);
+        let config = self.config.clone();
+        return Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let key = (config.key)(&parts).unwrap_or_default();
+            let decision = match config.store.check(&key, &config.quota).await {
+                Ok(decision) => decision,
+                Err(err) => {
+                    // rather serve requests unlimited than fail all of them
+                    tracing::warn!(error = %err, "rate limit store failed");
+                    return inner.call(Request::from_parts(parts, body)).await;
+                }
+            };
+
+            if !decision.allowed {
+                tracing::debug!(key, "rate limited request");
+                let mut res = Response::builder()
+                    .status(StatusCode::TOO_MANY_REQUESTS)
+                    .body("too many requests".to_string())
+                    .unwrap();
+                insert_headers(&mut res, &decision);
+                return Ok(res);
+            }
+            let mut res = inner.call(Request::from_parts(parts, body)).await?;
+            insert_headers(&mut res, &decision);
+            return Ok(res);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: f64) -> Duration {
+        return Duration::from_secs_f64(secs);
+    }
+
+    #[test]
+    fn burst() {
+        let quota = Quota::new(3, secs(3.0));
+        let mut tat = None;
+        for remaining in [2, 1, 0] {
+            let (decision, new_tat) = quota.check(tat, Duration::ZERO);
+            assert!(decision.allowed);
+            assert_eq!(decision.limit, 3);
+            assert_eq!(decision.remaining, remaining);
+            assert_eq!(decision.reset, secs(f64::from(3 - remaining)));
+            assert_eq!(decision.retry_after, None);
+            tat = Some(new_tat);
+        }
+
+        let (decision, new_tat) = quota.check(tat, Duration::ZERO);
+        assert!(!decision.allowed);
+        assert_eq!(decision.remaining, 0);
+        assert_eq!(decision.reset, secs(3.0));
+        assert_eq!(decision.retry_after, Some(secs(1.0)));
+        // rejected requests don't use up the quota
+        assert_eq!(Some(new_tat), tat);
+    }
+
+    #[test]
+    fn recovery() {
+        let quota = Quota::new(3, secs(3.0));
+        let (_, tat) = quota.check(None, Duration::ZERO);
+        let (_, tat) = quota.check(Some(tat), Duration::ZERO);
+        let (_, tat) = quota.check(Some(tat), Duration::ZERO);
+
+        // one request is replenished per emission interval
+        let (decision, tat) = quota.check(Some(tat), secs(1.0));
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 0);
+        let (decision, _) = quota.check(Some(tat), secs(1.5));
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, Some(secs(0.5)));
+
+        // idle clients get their full burst back, but not more
+        let (decision, _) = quota.check(Some(tat), secs(60.0));
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 2);
+    }
+
+    #[test]
+    fn with_burst() {
+        let quota = Quota::per_second(10).with_burst(1);
+        let (decision, tat) = quota.check(None, Duration::ZERO);
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 0);
+        let (decision, _) = quota.check(Some(tat), Duration::ZERO);
+        assert_eq!(decision.retry_after, Some(secs(0.1)));
+        let (decision, _) = quota.check(Some(tat), secs(0.1));
+        assert!(decision.allowed);
+    }
+
+    #[tokio::test]
+    async fn memory_store_evicts_idle_keys() {
+        let store = MemoryStore::new().sweep_interval(Duration::ZERO);
+        let short = Quota::new(1, Duration::from_millis(1));
+        let long = Quota::per_minute(1);
+
+        assert!(store.check("idle", &short).await.unwrap().allowed);
+        assert!(store.check("limited", &long).await.unwrap().allowed);
+        assert_eq!(store.len(), 2);
+        assert!(!store.check("limited", &long).await.unwrap().allowed);
+
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        // the sweep drops the key which has its full burst again, but keeps the limited one
+        assert!(!store.check("limited", &long).await.unwrap().allowed);
+        assert_eq!(store.len(), 1);
+    }
+}
@@ -0,0 +1,222 @@
+use std::{fmt, future::Future, pin::Pin, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use hyper::{
+    header::{HeaderName, HeaderValue},
+    http::request::Parts,
+    Request, Response,
+};
+use tower::{Layer, Service};
+
+use crate::http::request::FromRequestParts;
+
+/// the id of a request, as set by [`RequestIdLayer`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(HeaderValue);
+
+impl RequestId {
+    pub fn new(id: HeaderValue) -> Self {
+        return Self(id);
+    }
+
+    pub fn header_value(&self) -> &HeaderValue {
+        return &self.0;
+    }
+
+    pub fn as_str(&self) -> &str {
+        // ids are only created from strings or checked to be valid ones
+        return self.0.to_str().unwrap_or_default();
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromRequestParts for RequestId {
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        return parts
+            .extensions
+            .get::<RequestId>()
+            .cloned()
+            .ok_or_else(|| anyhow!("missing request id, is the RequestIdLayer applied?"));
+    }
+}
+
+type MakeRequestId = Arc<dyn Fn() -> String + Send + Sync>;
+
+/// tags every request with an id, which is stored in the request extensions, can be extracted
+/// with [`RequestId`] and is echoed in the response headers.
+///
+/// the id sent by the client in the `X-Request-Id` header is reused, otherwise a UUID is
+/// generated
+#[derive(Clone)]
+pub struct RequestIdLayer {
+    header: HeaderName,
+    make_id: MakeRequestId,
+    trust_incoming: bool,
+}
+
+impl Default for RequestIdLayer {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl RequestIdLayer {
+    pub fn new() -> Self {
+        return Self {
+            header: HeaderName::from_static("x-request-id"),
+            make_id: Arc::new(|| uuid::Uuid::new_v4().to_string()),
+            trust_incoming: true,
+        };
+    }
+
+    /// the header the id is read from and echoed in
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        return self;
+    }
+
+    /// generates ids with `f` instead of as UUIDs, for example to use ULIDs
+    pub fn make_id<F>(mut self, f: F) -> Self
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.make_id = Arc::new(f);
+        return self;
+    }
+
+    /// whether to reuse the id sent by the client, should be disabled when clients can't be
+    /// trusted to send unique ids
+    pub fn trust_incoming(mut self, trust: bool) -> Self {
+        self.trust_incoming = trust;
+        return self;
+    }
+
+    fn request_id(&self, parts: &Parts) -> RequestId {
+        if self.trust_incoming {
+            let incoming = parts
+                .headers
+                .get(&self.header)
+                .filter(|id| !id.is_empty() && id.len() <= 256 && id.to_str().is_ok());
+            if let Some(id) = incoming {
+                return RequestId(id.clone());
+            }
+        }
+        // falls back to a uuid if the generated id can't be sent in a header
+        let id = HeaderValue::from_str(&(self.make_id)())
+            .unwrap_or_else(|_| HeaderValue::from_str(&uuid::Uuid::new_v4().to_string()).unwrap());
+        return RequestId(id);
+    }
+}
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = SetRequestId<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return SetRequestId {
+            inner,
+            config: self.clone(),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct SetRequestId<S> {
+    inner: S,
+    config: RequestIdLayer,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SetRequestId<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let (mut parts, body) = req.into_parts();
+        let id = self.config.request_id(&parts);
+        parts
+            .headers
+            .insert(self.config.header.clone(), id.0.clone());
+        parts.extensions.insert(id.clone());
+
+        let header = self.config.header.clone();
+        let fut = self.inner.call(Request::from_parts(parts, body));
+        return Box::pin(async move {
+            let mut res = fut.await?;
+            res.headers_mut().entry(header).or_insert(id.0);
+            Ok(res)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tower::{service_fn, ServiceExt};
+
+    /// answers with the id the handler saw, returns it along with the response header
+    async fn call(layer: RequestIdLayer, incoming: Option<&str>) -> (String, String) {
+        let service = service_fn(|req: Request<String>| async move {
+            let (parts, _) = req.into_parts();
+            let id = RequestId::from_request_parts(&parts).unwrap();
+            // the request header is set too, e.g. for proxied requests
+            assert_eq!(parts.headers["x-request-id"], id.as_str());
+            return Ok::<_, Infallible>(Response::new(id.to_string()));
+        });
+        let mut req = Request::new(String::new());
+        if let Some(id) = incoming {
+            req.headers_mut()
+                .insert("x-request-id", HeaderValue::from_str(id).unwrap());
+        }
+        let res = layer.layer(service).oneshot(req).await.unwrap();
+        let header = res.headers()["x-request-id"].to_str().unwrap().to_string();
+        return (res.into_body(), header);
+    }
+
+    #[tokio::test]
+    async fn propagated() {
+        let (id, header) = call(RequestIdLayer::new(), Some("abc-123")).await;
+        assert_eq!(id, "abc-123");
+        assert_eq!(header, "abc-123");
+    }
+
+    #[tokio::test]
+    async fn generated() {
+        let (id, header) = call(RequestIdLayer::new(), None).await;
+        assert!(uuid::Uuid::parse_str(&id).is_ok());
+        assert_eq!(header, id);
+        let (other, _) = call(RequestIdLayer::new(), None).await;
+        assert_ne!(other, id);
+
+        let layer = RequestIdLayer::new().make_id(|| "custom".to_string());
+        assert_eq!(call(layer.clone(), None).await.0, "custom");
+        // incoming ids which are too long are replaced
+        assert_eq!(call(layer, Some(&"a".repeat(257))).await.0, "custom");
+    }
+
+    #[tokio::test]
+    async fn untrusted() {
+        let layer = RequestIdLayer::new()
+            .trust_incoming(false)
+            .make_id(|| "generated".to_string());
+        let (id, header) = call(layer, Some("abc-123")).await;
+        assert_eq!(id, "generated");
+        assert_eq!(header, "generated");
+    }
+}
@@ -0,0 +1,326 @@
+use std::{fmt, future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use hyper::{
+    header::{self, HeaderName, HeaderValue},
+    http::request::Parts,
+    Request, Response,
+};
+use tower::{Layer, Service};
+
+use crate::http::request::FromRequestParts;
+
+/// the placeholder in the content security policy which is replaced by the nonce of the request
+const NONCE_PLACEHOLDER: &str = "{nonce}";
+
+const DEFAULT_CSP: &str = "default-src 'self'; script-src 'self' 'nonce-{nonce}'; \
+                           object-src 'none'; base-uri 'self'; frame-ancestors 'none'";
+
+/// the nonce of the content security policy of a request, as generated by
+/// [`SecurityHeadersLayer`], to allow inline scripts with `<script nonce="...">`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CspNonce(Arc<str>);
+
+impl CspNonce {
+    fn generate() -> Self {
+        // 122 random bits, hex only uses characters allowed in a nonce
+        return Self(uuid::Uuid::new_v4().simple().to_string().into());
+    }
+
+    pub fn as_str(&self) -> &str {
+        return &self.0;
+    }
+}
+
+impl fmt::Display for CspNonce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromRequestParts for CspNonce {
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        return parts.extensions.get::<CspNonce>().cloned().ok_or_else(|| {
+            anyhow!("missing csp nonce, is the SecurityHeadersLayer applied with a policy using {{nonce}}?")
+        });
+    }
+}
+
+/// sends the headers every browser facing service should send, headers already set by the
+/// handler are kept.
+///
+/// by default these are
+/// - `Strict-Transport-Security: max-age=31536000; includeSubDomains`
+/// - `X-Content-Type-Options: nosniff`
+/// - `X-Frame-Options: DENY`
+/// - `Referrer-Policy: strict-origin-when-cross-origin`
+/// - `Content-Security-Policy: default-src 'self'; script-src 'self' 'nonce-{nonce}'; object-src 'none'; base-uri 'self'; frame-ancestors 'none'`
+///
+/// every `{nonce}` in the policy is replaced by a new nonce per request, which handlers can
+/// extract with [`CspNonce`]
+///
+/// ```ignore
+/// async fn index(nonce: CspNonce) -> impl IntoResponse {
+///     format!("<script nonce=\"{}\">alert(1)</script>", nonce)
+/// }
+///
+/// let router = Router::new()
+///     .route("/", get(index))
+///     .layer(SecurityHeadersLayer::new().frame_options("SAMEORIGIN"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct SecurityHeadersLayer {
+    headers: Vec<(HeaderName, HeaderValue)>,
+    csp: Option<String>,
+    csp_report_only: bool,
+}
+
+impl Default for SecurityHeadersLayer {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl SecurityHeadersLayer {
+    pub fn new() -> Self {
+        return Self {
+            headers: Vec::new(),
+            csp: Some(DEFAULT_CSP.to_string()),
+            csp_report_only: false,
+        }
+        .hsts(Duration::from_secs(365 * 24 * 60 * 60), true)
+        .header(
+            header::X_CONTENT_TYPE_OPTIONS,
+            HeaderValue::from_static("nosniff"),
+        )
+        .frame_options("DENY")
+        .referrer_policy("strict-origin-when-cross-origin");
+    }
+
+    /// sends `name` with `value`, replacing the default value if there is one
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.retain(|(header, _)| *header != name);
+        self.headers.push((name, value));
+        return self;
+    }
+
+    /// doesn't send `name`, `Content-Security-Policy` is disabled with
+    /// [`SecurityHeadersLayer::no_content_security_policy`]
+    pub fn without(mut self, name: HeaderName) -> Self {
+        self.headers.retain(|(header, _)| *header != name);
+        return self;
+    }
+
+    pub fn hsts(self, max_age: Duration, include_subdomains: bool) -> Self {
+        let mut value = format!("max-age={}", max_age.as_secs());
+        if include_subdomains {
+            value.push_str("; includeSubDomains");
+        }
+        return self.header(
+            header::STRICT_TRANSPORT_SECURITY,
+            HeaderValue::from_str(&value).unwrap(),
+        );
+    }
+
+    /// `DENY` or `SAMEORIGIN`
+    pub fn frame_options(self, value: &'static str) -> Self {
+        return self.header(header::X_FRAME_OPTIONS, HeaderValue::from_static(value));
+    }
+
+    pub fn referrer_policy(self, value: &'static str) -> Self {
+        return self.header(header::REFERRER_POLICY, HeaderValue::from_static(value));
+    }
+
+    /// replaces the default policy, every `{nonce}` in it is replaced by the nonce of the request
+    ///
+    /// # Panics
+    /// panics if the policy can't be sent in a header
+    pub fn content_security_policy(mut self, policy: impl Into<String>) -> Self {
+        let policy = policy.into();
+        HeaderValue::from_str(&policy).expect("invalid content security policy");
+        self.csp = Some(policy);
+        return self;
+    }
+
+    pub fn no_content_security_policy(mut self) -> Self {
+        self.csp = None;
+        return self;
+    }
+
+    /// sends the policy as `Content-Security-Policy-Report-Only`, so violations are only
+    /// reported, which helps to roll out a new policy
+    pub fn csp_report_only(mut self, report_only: bool) -> Self {
+        self.csp_report_only = report_only;
+        return self;
+    }
+}
+
+impl<S> Layer<S> for SecurityHeadersLayer {
+    type Service = SecurityHeaders<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return SecurityHeaders {
+            inner,
+            config: Arc::new(self.clone()),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct SecurityHeaders<S> {
+    inner: S,
+    config: Arc<SecurityHeadersLayer>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SecurityHeaders<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let csp = self.config.csp.as_ref().map(|policy| {
+            if !policy.contains(NONCE_PLACEHOLDER) {
+                return policy.clone();
+            }
+            let nonce = CspNonce::generate();
+            let policy = policy.replace(NONCE_PLACEHOLDER, nonce.as_str());
+            req.extensions_mut().insert(nonce);
+            policy
+        });
+
+        let config = self.config.clone();
+        let fut = self.inner.call(req);
+        return Box::pin(async move {
+            let mut res = fut.await?;
+            let headers = res.headers_mut();
+            for (name, value) in &config.headers {
+                headers.entry(name).or_insert_with(|| value.clone());
+            }
+            if let Some(csp) = csp {
+                let name = match config.csp_report_only {
+                    true => header::CONTENT_SECURITY_POLICY_REPORT_ONLY,
+                    false => header::CONTENT_SECURITY_POLICY,
+                };
+                // the policy was checked when it was set and the nonce is hex
+                headers
+                    .entry(name)
+                    .or_insert_with(|| HeaderValue::from_str(&csp).unwrap());
+            }
+            Ok(res)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tower::{service_fn, ServiceExt};
+
+    /// responds with the headers of the request and its nonce, or an empty body if there is none
+    async fn call_with(layer: SecurityHeadersLayer, req: Request<String>) -> Response<String> {
+        let service = service_fn(|req: Request<String>| async move {
+            let (parts, _) = req.into_parts();
+            let nonce = CspNonce::from_request_parts(&parts)
+                .map(|nonce| nonce.to_string())
+                .unwrap_or_default();
+            let mut res = Response::new(nonce);
+            *res.headers_mut() = parts.headers;
+            return Ok::<_, Infallible>(res);
+        });
+        return layer.layer(service).oneshot(req).await.unwrap();
+    }
+
+    async fn call(layer: SecurityHeadersLayer) -> Response<String> {
+        return call_with(layer, Request::new(String::new())).await;
+    }
+
+    #[tokio::test]
+    async fn defaults() {
+        let res = call(SecurityHeadersLayer::new()).await;
+        let headers = res.headers();
+        assert_eq!(
+            headers[header::STRICT_TRANSPORT_SECURITY],
+            "max-age=31536000; includeSubDomains"
+        );
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(
+            headers[header::REFERRER_POLICY],
+            "strict-origin-when-cross-origin"
+        );
+        assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+
+        let nonce = res.body();
+        assert_eq!(nonce.len(), 32);
+        assert_eq!(
+            headers[header::CONTENT_SECURITY_POLICY],
+            DEFAULT_CSP.replace(NONCE_PLACEHOLDER, nonce).as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn set_by_handler() {
+        let req = Request::get("/")
+            .header(header::X_FRAME_OPTIONS, "SAMEORIGIN")
+            .header(header::CONTENT_SECURITY_POLICY, "default-src 'none'")
+            .body(String::new())
+            .unwrap();
+        let res = call_with(SecurityHeadersLayer::new(), req).await;
+        assert_eq!(res.headers()[header::X_FRAME_OPTIONS], "SAMEORIGIN");
+        assert_eq!(
+            res.headers()[header::CONTENT_SECURITY_POLICY],
+            "default-src 'none'"
+        );
+        assert_eq!(res.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+    }
+
+    #[tokio::test]
+    async fn nonce() {
+        let layer = SecurityHeadersLayer::new()
+            .content_security_policy("script-src 'nonce-{nonce}'; style-src 'nonce-{nonce}'");
+        let first = call(layer.clone()).await;
+        let nonce = first.body();
+        assert_eq!(
+            first.headers()[header::CONTENT_SECURITY_POLICY],
+            format!("script-src 'nonce-{}'; style-src 'nonce-{}'", nonce, nonce).as_str()
+        );
+        let second = call(layer).await;
+        assert_ne!(second.body(), nonce);
+    }
+
+    #[tokio::test]
+    async fn without_nonce() {
+        let layer = SecurityHeadersLayer::new()
+            .content_security_policy("default-src 'self'")
+            .csp_report_only(true);
+        let res = call(layer).await;
+        assert_eq!(res.body(), "");
+        assert!(!res.headers().contains_key(header::CONTENT_SECURITY_POLICY));
+        assert_eq!(
+            res.headers()[header::CONTENT_SECURITY_POLICY_REPORT_ONLY],
+            "default-src 'self'"
+        );
+
+        let layer = SecurityHeadersLayer::new()
+            .no_content_security_policy()
+            .without(header::STRICT_TRANSPORT_SECURITY);
+        let res = call(layer).await;
+        assert_eq!(res.body(), "");
+        assert!(!res.headers().contains_key(header::CONTENT_SECURITY_POLICY));
+        assert!(!res
+            .headers()
+            .contains_key(header::STRICT_TRANSPORT_SECURITY));
+        assert_eq!(res.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+    }
+}
@@ -0,0 +1,776 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    io, mem,
+    path::PathBuf,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Result};
+use hyper::{http::request::Parts, Request, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tower::{Layer, Service};
+
+use crate::http::{
+    cookie::{time, Cookie, CookieJar, SameSite},
+    request::FromRequestParts,
+    response::{IntoResponseParts, Response},
+};
+
+/// a session as it is kept in a [`SessionStore`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Record {
+    pub id: String,
+    pub data: HashMap<String, Value>,
+    pub created_at: SystemTime,
+    /// when the session expires, either because it was idle or reached its maximum age
+    pub expires_at: SystemTime,
+}
+
+impl Record {
+    pub fn is_expired(&self) -> bool {
+        return self.expires_at <= SystemTime::now();
+    }
+}
+
+type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// where sessions are kept, expired records don't have to be returned by `load`
+pub trait SessionStore: Send + Sync + 'static {
+    fn load<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Record>>;
+    fn save<'a>(&'a self, record: &'a Record) -> StoreFuture<'a, ()>;
+    fn delete<'a>(&'a self, id: &'a str) -> StoreFuture<'a, ()>;
+}
+
+/// keeps the sessions in memory, so they are lost when the server restarts. expired sessions
+/// are removed periodically
+pub struct MemoryStore {
+    sweep_interval: Duration,
+    state: Mutex<MemoryState>,
+}
+
+struct MemoryState {
+    records: HashMap<String, Record>,
+    last_sweep: SystemTime,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        return Self {
+            sweep_interval: Duration::from_secs(60),
+            state: Mutex::new(MemoryState {
+                records: HashMap::new(),
+                last_sweep: SystemTime::now(),
+            }),
+        };
+    }
+
+    /// how often expired sessions are removed
+    pub fn sweep_interval(mut self, interval: Duration) -> Self {
+        self.sweep_interval = interval;
+        return self;
+    }
+
+    pub fn len(&self) -> usize {
+        return self
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .records
+            .len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Record>> {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let record = state
+            .records
+            .get(id)
+            .filter(|record| !record.is_expired())
+            .cloned();
+        return Box::pin(std::future::ready(Ok(record)));
+    }
+
+    fn save<'a>(&'a self, record: &'a Record) -> StoreFuture<'a, ()> {
+        let now = SystemTime::now();
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let since_sweep = now.duration_since(state.last_sweep).unwrap_or_default();
+        if since_sweep >= self.sweep_interval {
+            state.records.retain(|_, record| record.expires_at > now);
+            state.last_sweep = now;
+        }
+        state.records.insert(record.id.clone(), record.clone());
+        return Box::pin(std::future::ready(Ok(())));
+    }
+
+    fn delete<'a>(&'a self, id: &'a str) -> StoreFuture<'a, ()> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.records.remove(id);
+        return Box::pin(std::future::ready(Ok(())));
+    }
+}
+
+/// keeps every session as a json file in a directory, expired files are only removed when they
+/// are loaded or by [`FileStore::remove_expired`]. files which can't be parsed, e.g. because they
+/// were written by an incompatible version, are removed and the session is treated as missing
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    /// the directory is created when the first session is saved
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        return Self { dir: dir.into() };
+    }
+
+    fn path(&self, id: &str) -> Option<PathBuf> {
+        // ids come from cookies, so they must not be able to point outside of the directory
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        return Some(self.dir.join(format!("{}.json", id)));
+    }
+
+    /// deletes the files of all expired sessions
+    pub async fn remove_expired(&self) -> Result<()> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let Some(id) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_suffix(".json").map(str::to_string))
+            else {
+                continue;
+            };
+            // loading removes the file if it expired
+            self.load(&id).await?;
+        }
+        return Ok(());
+    }
+}
+
+impl SessionStore for FileStore {
+    fn load<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Record>> {
+        return Box::pin(async move {
+            let Some(path) = self.path(id) else {
+                return Ok(None);
+            };
+            let contents = match tokio::fs::read(&path).await {
+                Ok(contents) => contents,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => return Err(err.into()),
+            };
+            let record: Record = match serde_json::from_slice(&contents) {
+                Ok(record) => record,
+                Err(err) => {
+                    tracing::warn!(error = %err, path = %path.display(), "invalid session file");
+                    self.delete(id).await?;
+                    return Ok(None);
+                }
+            };
+            if record.is_expired() {
+                self.delete(id).await?;
+                return Ok(None);
+            }
+            return Ok(Some(record));
+        });
+    }
+
+    fn save<'a>(&'a self, record: &'a Record) -> StoreFuture<'a, ()> {
+        return Box::pin(async move {
+            let path = self
+                .path(&record.id)
+                .ok_or_else(|| anyhow!("invalid session id"))?;
+            tokio::fs::create_dir_all(&self.dir).await?;
+            // written to a temporary file first, so a crash never leaves half a session behind
+            let tmp = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4().simple()));
+            tokio::fs::write(&tmp, serde_json::to_vec(record)?).await?;
+            tokio::fs::rename(&tmp, &path).await?;
+            return Ok(());
+        });
+    }
+
+    fn delete<'a>(&'a self, id: &'a str) -> StoreFuture<'a, ()> {
+        return Box::pin(async move {
+            let Some(path) = self.path(id) else {
+                return Ok(());
+            };
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => return Ok(()),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+                Err(err) => return Err(err.into()),
+            }
+        });
+    }
+}
+
+struct SessionState {
+    id: Option<String>,
+    data: HashMap<String, Value>,
+    created_at: SystemTime,
+    expires_at: Option<SystemTime>,
+    modified: bool,
+    cycle_id: bool,
+    destroyed: bool,
+}
+
+/// the session of the request, as loaded by [`SessionLayer`]. changes are saved once the
+/// response is ready
+///
+/// ```ignore
+/// async fn login(session: Session, Json(login): Json<Login>) -> Result<(), StatusCode> {
+///     let user = check_password(&login)?;
+///     // a new id prevents session fixation
+///     session.cycle_id();
+///     session.insert("user_id", user.id)?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Session(Arc<Mutex<SessionState>>);
+
+impl Session {
+    fn lock(&self) -> std::sync::MutexGuard<'_, SessionState> {
+        return self.0.lock().unwrap_or_else(PoisonError::into_inner);
+    }
+
+    /// the id of the session, `None` if it is new and hasn't been saved yet
+    pub fn id(&self) -> Option<String> {
+        return self.lock().id.clone();
+    }
+
+    /// the value of `key`, `None` if it is missing or not a `T`
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let value = self.lock().data.get(key)?.clone();
+        return serde_json::from_value(value).ok();
+    }
+
+    pub fn insert<T: Serialize>(&self, key: impl Into<String>, value: T) -> Result<()> {
+        let value = serde_json::to_value(value)?;
+        let mut state = self.lock();
+        state.data.insert(key.into(), value);
+        state.modified = true;
+        return Ok(());
+    }
+
+    /// removes `key` and returns its value, `None` if it is missing or not a `T`
+    pub fn remove<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let mut state = self.lock();
+        let value = state.data.remove(key)?;
+        state.modified = true;
+        return serde_json::from_value(value).ok();
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.lock();
+        state.data.clear();
+        state.modified = true;
+    }
+
+    /// moves the data to a new id, which should be done whenever the privileges of the user
+    /// change, e.g. on login
+    pub fn cycle_id(&self) {
+        let mut state = self.lock();
+        state.cycle_id = true;
+        state.modified = true;
+    }
+
+    /// deletes the session from the store and the cookie from the client
+    pub fn destroy(&self) {
+        let mut state = self.lock();
+        state.data.clear();
+        state.destroyed = true;
+    }
+}
+
+impl FromRequestParts for Session {
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        return parts
+            .extensions
+            .get::<Session>()
+            .cloned()
+            .ok_or_else(|| anyhow!("missing session, is the SessionLayer applied?"));
+    }
+}
+
+/// loads the session of the request from a [`SessionStore`], using the id in a cookie, and
+/// makes it available through the [`Session`] extractor.
+///
+/// sessions expire after being idle for the idle timeout, 24 hours by default, and at the latest
+/// after the absolute timeout, 7 days by default. they are only saved when they were modified,
+/// or to extend the idle timeout by at least half of it, and the cookie is only sent when the
+/// session is saved.
+///
+/// requests whose session can't be loaded are answered with `500 Internal Server Error`. if the
+/// session can't be saved once the handler ran, the error is logged and the response is sent
+/// without the session cookie, since the handler's side effects already happened
+///
+/// ```ignore
+/// let router = Router::new()
+///     .route("/admin", get(admin))
+///     .layer(SessionLayer::new(FileStore::new("sessions")).idle_timeout(Duration::from_secs(3600)));
+/// ```
+#[derive(Clone)]
+pub struct SessionLayer {
+    store: Arc<dyn SessionStore>,
+    cookie_name: String,
+    path: String,
+    secure: bool,
+    same_site: SameSite,
+    idle_timeout: Duration,
+    absolute_timeout: Duration,
+}
+
+impl SessionLayer {
+    pub fn new<S: SessionStore>(store: S) -> Self {
+        return Self {
+            store: Arc::new(store),
+            cookie_name: "session".to_string(),
+            path: "/".to_string(),
+            secure: true,
+            same_site: SameSite::Lax,
+            idle_timeout: Duration::from_secs(24 * 60 * 60),
+            absolute_timeout: Duration::from_secs(7 * 24 * 60 * 60),
+        };
+    }
+
+    pub fn cookie_name(mut self, name: impl Into<String>) -> Self {
+        self.cookie_name = name.into();
+        return self;
+    }
+
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        return self;
+    }
+
+    /// whether the cookie is only sent over https, which should only be disabled in development
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        return self;
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = same_site;
+        return self;
+    }
+
+    /// how long a session lasts without requests
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        return self;
+    }
+
+    /// how long a session lasts at most, no matter how active it is
+    pub fn absolute_timeout(mut self, timeout: Duration) -> Self {
+        self.absolute_timeout = timeout;
+        return self;
+    }
+
+    fn cookie(&self, value: String) -> Cookie<'static> {
+        let mut cookie = Cookie::new(self.cookie_name.clone(), value);
+        cookie.set_path(self.path.clone());
+        cookie.set_http_only(true);
+        cookie.set_secure(self.secure);
+        cookie.set_same_site(self.same_site);
+        return cookie;
+    }
+
+    async fn load(&self, parts: &Parts) -> Result<SessionState> {
+        let jar = CookieJar::from_headers(&parts.headers);
+        let record = match jar.get(&self.cookie_name) {
+            Some(cookie) => self.store.load(cookie.value()).await?,
+            None => None,
+        };
+        let state = match record.filter(|record| !record.is_expired()) {
+            Some(record) => SessionState {
+                id: Some(record.id),
+                data: record.data,
+                created_at: record.created_at,
+                expires_at: Some(record.expires_at),
+                modified: false,
+                cycle_id: false,
+                destroyed: false,
+            },
+            None => SessionState {
+                id: None,
+                data: HashMap::new(),
+                created_at: SystemTime::now(),
+                expires_at: None,
+                modified: false,
+                cycle_id: false,
+                destroyed: false,
+            },
+        };
+        return Ok(state);
+    }
+
+    /// saves or deletes the session if needed, returning the cookies to send
+    async fn store(&self, state: SessionState) -> Result<CookieJar> {
+        let jar = CookieJar::new();
+        let now = SystemTime::now();
+
+        if state.destroyed || (state.modified && state.data.is_empty()) {
+            let Some(id) = state.id else {
+                return Ok(jar);
+            };
+            self.store.delete(&id).await?;
+            let mut cookie = self.cookie(String::new());
+            cookie.make_removal();
+            return Ok(jar.add(cookie));
+        }
+
+        // unmodified sessions are only saved to extend the idle timeout, once that gains at
+        // least half of it
+        let expires_at = (now + self.idle_timeout).min(state.created_at + self.absolute_timeout);
+        let refresh = state.expires_at.map_or(false, |old| {
+            let extension = expires_at.duration_since(old).unwrap_or_default();
+            extension > self.idle_timeout / 2
+        });
+        if !state.modified && !refresh {
+            return Ok(jar);
+        }
+
+        let id = match state.id {
+            Some(id) if state.cycle_id => {
+                self.store.delete(&id).await?;
+                None
+            }
+            id => id,
+        };
+        let record = Record {
+            id: id.unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string()),
+            data: state.data,
+            created_at: state.created_at,
+            expires_at,
+        };
+        self.store.save(&record).await?;
+
+        let mut cookie = self.cookie(record.id);
+        let max_age = record.expires_at.duration_since(now).unwrap_or_default();
+        cookie.set_max_age(time::Duration::seconds(max_age.as_secs() as i64));
+        return Ok(jar.add(cookie));
+    }
+}
+
+impl<S> Layer<S> for SessionLayer {
+    type Service = SessionManager<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return SessionManager {
+            inner,
+            config: self.clone(),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct SessionManager<S> {
+    inner: S,
+    config: SessionLayer,
+}
+
+fn load_failed(err: anyhow::Error) -> Response {
+    tracing::error!(error = %err, "failed to load session");
+    return Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body("internal server error".to_string())
+        .unwrap();
+}
+
+impl<S, B> Service<Request<B>> for SessionManager<S>
+where
+    S: Service<Request<B>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        // the ready service has to handle the request, the clone waits for the next one
+        let clone = self.inner.clone();
+        let mut inner = mem::replace(&mut self.inner, clone);
+        let config = self.config.clone();
+        return Box::pin(async move {
+            let (mut parts, body) = req.into_parts();
+            let state = match config.load(&parts).await {
+                Ok(state) => state,
+                Err(err) => return Ok(load_failed(err)),
+            };
+            let session = Session(Arc::new(Mutex::new(state)));
+            parts.extensions.insert(session.clone());
+
+            let mut res = inner.call(Request::from_parts(parts, body)).await?;
+
+            // handlers could keep a clone of the session around, so the state is taken out
+            let state = {
+                let mut state = session.lock();
+                SessionState {
+                    id: state.id.take(),
+                    data: mem::take(&mut state.data),
+                    ..*state
+                }
+            };
+            match config.store(state).await {
+                Ok(jar) => jar.into_response_parts(&mut res),
+                Err(err) => tracing::error!(error = %err, "failed to save session"),
+            }
+            return Ok(res);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::{service_fn, ServiceExt};
+
+    /// a [`MemoryStore`] counting the writes
+    #[derive(Clone, Default)]
+    struct CountingStore(Arc<(MemoryStore, AtomicUsize, AtomicUsize)>);
+
+    impl CountingStore {
+        fn saves(&self) -> usize {
+            return self.0 .1.load(Ordering::SeqCst);
+        }
+
+        fn deletes(&self) -> usize {
+            return self.0 .2.load(Ordering::SeqCst);
+        }
+    }
+
+    impl SessionStore for CountingStore {
+        fn load<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Record>> {
+            return self.0 .0.load(id);
+        }
+
+        fn save<'a>(&'a self, record: &'a Record) -> StoreFuture<'a, ()> {
+            self.0 .1.fetch_add(1, Ordering::SeqCst);
+            return self.0 .0.save(record);
+        }
+
+        fn delete<'a>(&'a self, id: &'a str) -> StoreFuture<'a, ()> {
+            self.0 .2.fetch_add(1, Ordering::SeqCst);
+            return self.0 .0.delete(id);
+        }
+    }
+
+    /// calls `handler` with the session of a request sending `id` as its cookie, returns the
+    /// response body and the session cookie sent back, if any
+    async fn call(
+        layer: &SessionLayer,
+        id: Option<&str>,
+        handler: fn(&Session) -> String,
+    ) -> (String, Option<Cookie<'static>>) {
+        let service = service_fn(move |req: Request<String>| async move {
+            let session = req.extensions().get::<Session>().unwrap();
+            return Ok::<_, std::convert::Infallible>(Response::new(handler(session)));
+        });
+        let mut req = Request::new(String::new());
+        if let Some(id) = id {
+            let cookie = format!("session={}", id);
+            req.headers_mut()
+                .insert(header::COOKIE, cookie.parse().unwrap());
+        }
+        let res = layer.clone().layer(service).oneshot(req).await.unwrap();
+        let cookie = res
+            .headers()
+            .get(header::SET_COOKIE)
+            .map(|value| Cookie::parse(value.to_str().unwrap().to_string()).unwrap());
+        return (res.into_body(), cookie);
+    }
+
+    fn count(session: &Session) -> String {
+        let count = session.get::<u32>("count").unwrap_or(0) + 1;
+        session.insert("count", count).unwrap();
+        return count.to_string();
+    }
+
+    fn read(session: &Session) -> String {
+        return session.get::<u32>("count").unwrap_or(0).to_string();
+    }
+
+    #[tokio::test]
+    async fn only_saved_when_changed() {
+        let store = CountingStore::default();
+        let layer = SessionLayer::new(store.clone());
+
+        // new sessions without data are never saved
+        let (_, cookie) = call(&layer, None, read).await;
+        assert!(cookie.is_none());
+        assert_eq!(store.saves(), 0);
+
+        let (body, cookie) = call(&layer, None, count).await;
+        assert_eq!(body, "1");
+        let cookie = cookie.unwrap();
+        assert!(cookie.http_only().unwrap());
+        assert_eq!(store.saves(), 1);
+
+        let (body, unchanged) = call(&layer, Some(cookie.value()), read).await;
+        assert_eq!(body, "1");
+        assert!(unchanged.is_none());
+        assert_eq!(store.saves(), 1);
+
+        let (body, changed) = call(&layer, Some(cookie.value()), count).await;
+        assert_eq!(body, "2");
+        assert_eq!(changed.unwrap().value(), cookie.value());
+        assert_eq!(store.saves(), 2);
+    }
+
+    #[tokio::test]
+    async fn idle_timeout() {
+        let store = CountingStore::default();
+        let layer = SessionLayer::new(store.clone()).idle_timeout(Duration::from_millis(500));
+        let (_, cookie) = call(&layer, None, count).await;
+        let id = cookie.unwrap().value().to_string();
+
+        // reading the session after more than half of the idle timeout extends it
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let (body, refreshed) = call(&layer, Some(&id), read).await;
+        assert_eq!(body, "1");
+        assert_eq!(refreshed.unwrap().value(), id);
+        assert_eq!(store.saves(), 2);
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(call(&layer, Some(&id), read).await.0, "1");
+
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert_eq!(call(&layer, Some(&id), read).await.0, "0");
+    }
+
+    #[tokio::test]
+    async fn absolute_timeout() {
+        let layer =
+            SessionLayer::new(MemoryStore::new()).absolute_timeout(Duration::from_millis(500));
+        let (_, cookie) = call(&layer, None, count).await;
+        let id = cookie.unwrap().value().to_string();
+
+        // being active doesn't keep the session alive past the absolute timeout
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(call(&layer, Some(&id), count).await.0, "2");
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(call(&layer, Some(&id), read).await.0, "0");
+    }
+
+    #[tokio::test]
+    async fn cycle_id() {
+        let store = CountingStore::default();
+        let layer = SessionLayer::new(store.clone());
+        let (_, cookie) = call(&layer, None, count).await;
+        let old = cookie.unwrap().value().to_string();
+
+        let (_, cookie) = call(&layer, Some(&old), |session| {
+            session.cycle_id();
+            return count(session);
+        })
+        .await;
+        let new = cookie.unwrap().value().to_string();
+        assert_ne!(new, old);
+        assert_eq!(store.deletes(), 1);
+        assert!(store.load(&old).await.unwrap().is_none());
+
+        assert_eq!(call(&layer, Some(&old), read).await.0, "0");
+        assert_eq!(call(&layer, Some(&new), read).await.0, "2");
+    }
+
+    #[tokio::test]
+    async fn destroy() {
+        let store = CountingStore::default();
+        let layer = SessionLayer::new(store.clone());
+        let (_, cookie) = call(&layer, None, count).await;
+        let id = cookie.unwrap().value().to_string();
+
+        let (_, removal) = call(&layer, Some(&id), |session| {
+            session.destroy();
+            return String::new();
+        })
+        .await;
+        let removal = removal.unwrap();
+        assert_eq!(removal.value(), "");
+        assert_eq!(removal.max_age(), Some(time::Duration::ZERO));
+        assert!(store.load(&id).await.unwrap().is_none());
+        assert_eq!(call(&layer, Some(&id), read).await.0, "0");
+    }
+
+    struct FailingStore;
+
+    impl SessionStore for FailingStore {
+        fn load<'a>(&'a self, _: &'a str) -> StoreFuture<'a, Option<Record>> {
+            return Box::pin(std::future::ready(Ok(None)));
+        }
+
+        fn save<'a>(&'a self, _: &'a Record) -> StoreFuture<'a, ()> {
+            return Box::pin(std::future::ready(Err(anyhow!("store is down"))));
+        }
+
+        fn delete<'a>(&'a self, _: &'a str) -> StoreFuture<'a, ()> {
+            return Box::pin(std::future::ready(Err(anyhow!("store is down"))));
+        }
+    }
+
+    #[tokio::test]
+    async fn save_failure_keeps_response() {
+        let service = service_fn(|req: Request<String>| async move {
+            let session = req.extensions().get::<Session>().unwrap();
+            session.insert("user", 1).unwrap();
+            return Ok::<_, std::convert::Infallible>(Response::new("logged in".to_string()));
+        });
+        let res = SessionLayer::new(FailingStore)
+            .layer(service)
+            .oneshot(Request::new(String::new()))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body(), "logged in");
+        assert!(res.headers().get(header::SET_COOKIE).is_none());
+    }
+
+    #[tokio::test]
+    async fn file_store_invalid_record() {
+        let dir = std::env::temp_dir().join(format!("sessions-{}", uuid::Uuid::new_v4().simple()));
+        let store = FileStore::new(&dir);
+        let record = Record {
+            id: "valid".to_string(),
+            data: HashMap::new(),
+            created_at: SystemTime::now(),
+            expires_at: SystemTime::now() + Duration::from_secs(60),
+        };
+        store.save(&record).await.unwrap();
+        tokio::fs::write(dir.join("broken.json"), "{")
+            .await
+            .unwrap();
+
+        assert!(store.load("valid").await.unwrap().is_some());
+        assert!(store.load("broken").await.unwrap().is_none());
+        assert!(!dir.join("broken.json").exists());
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,66 @@
+use std::task::{Context, Poll};
+
+use hyper::Request;
+use tower::{Layer, Service};
+
+use crate::http::request::State;
+
+/// makes `state` available to every handler and middleware below it through the [`State`]
+/// extractor
+///
+/// ```ignore
+/// async fn handler(State(db): State<Db>) -> impl IntoResponse { ... }
+///
+/// let router = Router::new()
+///     .route("/", get(handler))
+///     .layer(StateLayer::new(db));
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct StateLayer<T> {
+    state: T,
+}
+
+impl<T> StateLayer<T> {
+    pub fn new(state: T) -> Self {
+        return Self { state };
+    }
+}
+
+impl<S, T> Layer<S> for StateLayer<T>
+where
+    T: Clone,
+{
+    type Service = AddState<S, T>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return AddState {
+            inner,
+            state: self.state.clone(),
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AddState<S, T> {
+    inner: S,
+    state: T,
+}
+
+impl<S, T, B> Service<Request<B>> for AddState<S, T>
+where
+    S: Service<Request<B>>,
+    T: Clone + Send + Sync + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        req.extensions_mut().insert(State(self.state.clone()));
+        return self.inner.call(req);
+    }
+}
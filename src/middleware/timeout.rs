@@ -0,0 +1,262 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    body::{Body, Bytes},
+    Request, StatusCode,
+};
+use tower::{Layer, Service};
+
+use crate::http::{
+    body::{self, BoxBody, BoxError},
+    response::Response,
+};
+
+fn timeout_response(status: StatusCode, body: &str) -> Response {
+    return Response::builder()
+        .status(status)
+        .body(body.to_string())
+        .unwrap();
+}
+
+/// responds with `408 Request Timeout` if the inner service takes longer than the timeout,
+/// instead of failing like tower's `TimeoutLayer`.
+///
+/// routes can get their own timeout with
+/// [`MethodRouter::timeout`](crate::router::method_router::MethodRouter::timeout)
+#[derive(Clone, Debug)]
+pub struct TimeoutLayer {
+    timeout: Duration,
+    status: StatusCode,
+    body: String,
+}
+
+impl TimeoutLayer {
+    pub fn new(timeout: Duration) -> Self {
+        return Self {
+            timeout,
+            status: StatusCode::REQUEST_TIMEOUT,
+            body: "request timed out".to_string(),
+        };
+    }
+
+    /// the status of the response sent on timeout, e.g. `503 Service Unavailable`
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        return self;
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = body.into();
+        return self;
+    }
+}
+
+impl<S> Layer<S> for TimeoutLayer {
+    type Service = Timeout<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return Timeout {
+            inner,
+            config: self.clone(),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct Timeout<S> {
+    inner: S,
+    config: TimeoutLayer,
+}
+
+impl<S, B> Service<Request<B>> for Timeout<S>
+where
+    S: Service<Request<B>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let fut = self.inner.call(req);
+        let config = self.config.clone();
+        return Box::pin(async move {
+            match tokio::time::timeout(config.timeout, fut).await {
+                Ok(res) => res,
+                Err(_) => {
+                    tracing::debug!(timeout = ?config.timeout, "request timed out");
+                    Ok(timeout_response(config.status, &config.body))
+                }
+            }
+        });
+    }
+}
+
+/// reads the whole request body before passing the request on, and responds with
+/// `408 Request Timeout` if that takes longer than the timeout, so slow uploads can't hold on to
+/// a handler.
+///
+/// the timeout of [`TimeoutLayer`] starts once the body is read
+#[derive(Clone, Copy, Debug)]
+pub struct BodyTimeoutLayer {
+    timeout: Duration,
+}
+
+impl BodyTimeoutLayer {
+    pub fn new(timeout: Duration) -> Self {
+        return Self { timeout };
+    }
+}
+
+impl<S> Layer<S> for BodyTimeoutLayer {
+    type Service = BodyTimeout<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return BodyTimeout {
+            inner,
+            timeout: self.timeout,
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct BodyTimeout<S> {
+    inner: S,
+    timeout: Duration,
+}
+
+impl<S, B> Service<Request<B>> for BodyTimeout<S>
+where
+    S: Service<Request<BoxBody>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        // the ready service has to handle the request, the clone waits for the next one
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let timeout = self.timeout;
+        return Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let body = match tokio::time::timeout(timeout, body.collect()).await {
+                Ok(Ok(body)) => body.to_bytes(),
+                Ok(Err(_)) => {
+                    return Ok(timeout_response(
+                        StatusCode::BAD_REQUEST,
+                        "failed to read request body",
+                    ))
+                }
+                Err(_) => {
+                    tracing::debug!(?timeout, "timed out reading request body");
+                    return Ok(timeout_response(
+                        StatusCode::REQUEST_TIMEOUT,
+                        "timed out reading request body",
+                    ));
+                }
+            };
+            let req = Request::from_parts(parts, body::boxed(Full::new(body)));
+            inner.call(req).await
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::StreamBody;
+    use hyper::body::Frame;
+    use std::convert::Infallible;
+    use tower::{service_fn, ServiceExt};
+
+    async fn sleep(req: Request<String>) -> Result<Response, Infallible> {
+        let millis = req.body().parse().unwrap();
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        return Ok(Response::new("hello".to_string()));
+    }
+
+    async fn echo(req: Request<BoxBody>) -> Result<Response, Infallible> {
+        let body = req.into_body().collect().await.unwrap().to_bytes();
+        return Ok(Response::new(String::from_utf8(body.to_vec()).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn in_time() {
+        let layer = TimeoutLayer::new(Duration::from_millis(200));
+        let res = layer
+            .layer(service_fn(sleep))
+            .oneshot(Request::new("10".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body(), "hello");
+    }
+
+    #[tokio::test]
+    async fn timed_out() {
+        let layer = TimeoutLayer::new(Duration::from_millis(50));
+        let res = layer
+            .layer(service_fn(sleep))
+            .oneshot(Request::new("1000".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::REQUEST_TIMEOUT);
+        assert_eq!(res.body(), "request timed out");
+
+        let layer = TimeoutLayer::new(Duration::from_millis(50))
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body("try again later");
+        let res = layer
+            .layer(service_fn(sleep))
+            .oneshot(Request::new("1000".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.body(), "try again later");
+    }
+
+    #[tokio::test]
+    async fn body_in_time() {
+        let layer = BodyTimeoutLayer::new(Duration::from_millis(200));
+        let res = layer
+            .layer(service_fn(echo))
+            .oneshot(Request::new(Full::new(Bytes::from("hello"))))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body(), "hello");
+    }
+
+    #[tokio::test]
+    async fn body_timed_out() {
+        let body = StreamBody::new(futures_util::stream::pending::<
+            Result<Frame<Bytes>, Infallible>,
+        >());
+        let layer = BodyTimeoutLayer::new(Duration::from_millis(50));
+        let res = layer
+            .layer(service_fn(echo))
+            .oneshot(Request::new(body))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::REQUEST_TIMEOUT);
+        assert_eq!(res.body(), "timed out reading request body");
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+use hyper::{Request, Response, StatusCode};
+use tower::{Layer, Service};
+use tracing::{field, Instrument, Span};
+
+use crate::router::MatchedPath;
+
+/// called before the request is passed on, inside of the request span
+pub trait OnRequest<B> {
+    fn on_request(&self, req: &Request<B>, span: &Span);
+}
+
+/// called once the response is ready, inside of the request span
+pub trait OnResponse<B> {
+    fn on_response(&self, res: &Response<B>, latency: Duration, span: &Span);
+}
+
+/// called when the service failed or responded with a server error, inside of the request span
+pub trait OnFailure {
+    fn on_failure(&self, failure: &Failure, latency: Duration, span: &Span);
+}
+
+impl<B, F> OnRequest<B> for F
+where
+    F: Fn(&Request<B>, &Span),
+{
+    fn on_request(&self, req: &Request<B>, span: &Span) {
+        self(req, span)
+    }
+}
+
+impl<B, F> OnResponse<B> for F
+where
+    F: Fn(&Response<B>, Duration, &Span),
+{
+    fn on_response(&self, res: &Response<B>, latency: Duration, span: &Span) {
+        self(res, latency, span)
+    }
+}
+
+impl<F> OnFailure for F
+where
+    F: Fn(&Failure, Duration, &Span),
+{
+    fn on_failure(&self, failure: &Failure, latency: Duration, span: &Span) {
+        self(failure, latency, span)
+    }
+}
+
+/// why a request failed
+#[derive(Debug)]
+pub enum Failure {
+    /// the response has a 5xx status
+    Status(StatusCode),
+    /// the service returned an error
+    Error(String),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Status(status) => write!(f, "responded with {}", status),
+            Failure::Error(err) => f.write_str(err),
+        }
+    }
+}
+
+/// emits a debug event when a request arrives
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultOnRequest;
+
+impl<B> OnRequest<B> for DefaultOnRequest {
+    fn on_request(&self, _req: &Request<B>, _span: &Span) {
+        tracing::debug!("started processing request");
+    }
+}
+
+/// emits an info event with the status and latency of the response
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultOnResponse;
+
+impl<B> OnResponse<B> for DefaultOnResponse {
+    fn on_response(&self, res: &Response<B>, latency: Duration, _span: &Span) {
+        tracing::info!(
+            status = res.status().as_u16(),
+            latency_ms = latency.as_millis() as u64,
+            "finished processing request"
+        );
+    }
+}
+
+/// emits an error event describing the failure
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultOnFailure;
+
+impl OnFailure for DefaultOnFailure {
+    fn on_failure(&self, failure: &Failure, latency: Duration, _span: &Span) {
+        tracing::error!(
+            %failure,
+            latency_ms = latency.as_millis() as u64,
+            "request failed"
+        );
+    }
+}
+
+/// wraps every request in a `request` span with the method, uri, matched route, status and
+/// latency, and calls the hooks as the request progresses
+///
+/// ```ignore
+/// let router = Router::new()
+///     .route("/", get(hello))
+///     .layer(TraceLayer::new().on_request(|req: &Request<BoxBody>, _: &Span| {
+///         tracing::info!(user_agent = ?req.headers().get(header::USER_AGENT), "request");
+///     }));
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraceLayer<Req = DefaultOnRequest, Res = DefaultOnResponse, Fail = DefaultOnFailure> {
+    on_request: Req,
+    on_response: Res,
+    on_failure: Fail,
+}
+
+impl TraceLayer {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+}
+
+impl<Req, Res, Fail> TraceLayer<Req, Res, Fail> {
+    pub fn on_request<T>(self, on_request: T) -> TraceLayer<T, Res, Fail> {
+        return TraceLayer {
+            on_request,
+            on_response: self.on_response,
+            on_failure: self.on_failure,
+        };
+    }
+
+    pub fn on_response<T>(self, on_response: T) -> TraceLayer<Req, T, Fail> {
+        return TraceLayer {
+            on_request: self.on_request,
+            on_response,
+            on_failure: self.on_failure,
+        };
+    }
+
+    pub fn on_failure<T>(self, on_failure: T) -> TraceLayer<Req, Res, T> {
+        return TraceLayer {
+            on_request: self.on_request,
+            on_response: self.on_response,
+            on_failure,
+        };
+    }
+}
+
+impl<S, Req, Res, Fail> Layer<S> for TraceLayer<Req, Res, Fail>
+where
+    Req: Clone,
+    Res: Clone,
+    Fail: Clone,
+{
+    type Service = Trace<S, Req, Res, Fail>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return Trace {
+            inner,
+            on_request: self.on_request.clone(),
+            on_response: self.on_response.clone(),
+            on_failure: self.on_failure.clone(),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct Trace<S, Req, Res, Fail> {
+    inner: S,
+    on_request: Req,
+    on_response: Res,
+    on_failure: Fail,
+}
+
+impl<S, ReqBody, ResBody, Req, Res, Fail> Service<Request<ReqBody>> for Trace<S, Req, Res, Fail>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: fmt::Display,
+    S::Future: Send + 'static,
+    Req: OnRequest<ReqBody>,
+    Res: OnResponse<ResBody> + Clone + Send + 'static,
+    Fail: OnFailure + Clone + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let span = tracing::info_span!(
+            "request",
+            method = %req.method(),
+            uri = %req.uri(),
+            version = ?req.version(),
+            route = field::Empty,
+            status = field::Empty,
+            latency_ms = field::Empty,
+        );
+        let start = Instant::now();
+        let fut = {
+            let _guard = span.enter();
+            self.on_request.on_request(&req, &span);
+            self.inner.call(req)
+        };
+
+        let on_response = self.on_response.clone();
+        let on_failure = self.on_failure.clone();
+        let request_span = span.clone();
+        return Box::pin(
+            async move {
+                let res = fut.await;
+                let latency = start.elapsed();
+                let span = request_span;
+                span.record("latency_ms", latency.as_millis() as u64);
+                match &res {
+                    Ok(res) => {
+                        if let Some(route) = res.extensions().get::<MatchedPath>() {
+                            span.record("route", route.as_str());
+                        }
+                        span.record("status", res.status().as_u16());
+                        on_response.on_response(res, latency, &span);
+                        if res.status().is_server_error() {
+                            on_failure.on_failure(&Failure::Status(res.status()), latency, &span);
+                        }
+                    }
+                    Err(err) => {
+                        on_failure.on_failure(&Failure::Error(err.to_string()), latency, &span);
+                    }
+                }
+                res
+            }
+            .instrument(span),
+        );
+    }
+}
@@ -1,10 +1,14 @@
-use std::{future::Future, marker::PhantomData, pin::Pin};
+use std::{future::Future, marker::PhantomData, pin::Pin, sync::Arc};
 
+use hyper::StatusCode;
+use tower::{Layer, Service, ServiceExt};
 
 use crate::http::{
+    body::BoxError,
     request::{FromRequest, FromRequestParts, Request},
     response::{IntoResponse, Response},
 };
+use crate::router::method_router::Route;
 
 pub trait Handler: Send + Sync {
     //type Future: Future<Output = Response> + Send;
@@ -47,13 +51,19 @@ macro_rules! impl_function_handler {
                     $(
                         let $ty = match $ty::from_request_parts(&parts) {
                             Ok(value) => value,
-                            Err(err) => return err.into_response(),//panic!("failed to extract from request"),
+                            Err(err) => {
+                                tracing::debug!(error = %err, extractor = std::any::type_name::<$ty>(), "rejected request");
+                                return err.into_response();
+                            }
                         };
                     )*
                     let req = Request::from_parts(parts,body);
                     let $last = match $last::from_request(req) {
                         Ok(value) => value,
-                        Err(err) => return err.into_response(), //panic!("failed to extract from request"),
+                        Err(err) => {
+                            tracing::debug!(error = %err, extractor = std::any::type_name::<$last>(), "rejected request");
+                            return err.into_response();
+                        }
                     };
 
                     let res = (f)($($ty,)* $last).await;
@@ -158,3 +168,60 @@ impl_into_handler!(
 );
 
 pub type BoxedHandler = Box<dyn Handler>;
+
+/// a tower service used as a [`Handler`], created by [`HandlerExt::layer`]
+#[derive(Clone)]
+pub struct ServiceHandler<S>(pub S);
+
+impl<S> Handler for ServiceHandler<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + Sync + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+{
+    fn call(&self, request: Request) -> HandlerFuture {
+        let service = self.0.clone();
+        Box::pin(async move {
+            match service.oneshot(request).await {
+                Ok(res) => res,
+                Err(err) => {
+                    let err: BoxError = err.into();
+                    tracing::error!(error = %err, "handler service failed");
+                    Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body("internal server error".to_string())
+                        .unwrap()
+                }
+            }
+        })
+    }
+}
+
+impl<S> IntoHandler<ServiceHandler<S>> for ServiceHandler<S>
+where
+    ServiceHandler<S>: Handler,
+{
+    type Handler = Self;
+
+    fn into_handler(self) -> Self::Handler {
+        self
+    }
+}
+
+pub trait HandlerExt<T>: IntoHandler<T> {
+    /// wraps only this handler in `layer`
+    ///
+    /// ```ignore
+    /// Router::new().route("/", get(handler.layer(TimeoutLayer::new(Duration::new(1, 0)))))
+    /// ```
+    fn layer<L>(self, layer: L) -> ServiceHandler<L::Service>
+    where
+        Self::Handler: 'static,
+        L: Layer<Route>,
+    {
+        let route = Route(Arc::new(self.into_handler()));
+        return ServiceHandler(layer.layer(route));
+    }
+}
+
+impl<H, T> HandlerExt<T> for H where H: IntoHandler<T> {}
@@ -1,19 +1,100 @@
-use crate::router::handler::{Handler, IntoHandler};
+use crate::http::{body::BoxError, request::Request, response::Response};
+use crate::middleware::{concurrency_limit::ConcurrencyLimitLayer, timeout::TimeoutLayer};
+use crate::router::handler::{Handler, IntoHandler, ServiceHandler};
 use hyper::http;
-use std::sync::Arc;
+use std::{convert::Infallible, future::Future, pin::Pin, sync::Arc, time::Duration};
+use tower::{Layer, Service};
 
 #[derive(Clone)]
 pub struct Route(pub Arc<dyn Handler>);
 
+impl Route {
+    /// wraps the handler of this route in `layer`
+    pub fn layer<L>(self, layer: L) -> Route
+    where
+        L: Layer<Route>,
+        L::Service: Service<Request, Response = Response> + Clone + Send + Sync + 'static,
+        <L::Service as Service<Request>>::Future: Send,
+        <L::Service as Service<Request>>::Error: Into<BoxError>,
+    {
+        return Route(Arc::new(ServiceHandler(layer.layer(self))));
+    }
+}
+
+impl Service<Request> for Route {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let fut = self.0.call(request);
+        return Box::pin(async move { Ok(fut.await) });
+    }
+}
+
+/// a handler for one method, created by [`get`], [`post`] and the other method functions
+pub struct MethodRouter {
+    pub(crate) route: Route,
+    pub(crate) method: http::Method,
+}
+
+impl MethodRouter {
+    /// wraps the handler in `layer`, see [`Route::layer`]
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route>,
+        L::Service: Service<Request, Response = Response> + Clone + Send + Sync + 'static,
+        <L::Service as Service<Request>>::Future: Send,
+        <L::Service as Service<Request>>::Error: Into<BoxError>,
+    {
+        self.route = self.route.layer(layer);
+        return self;
+    }
+
+    /// responds with `408 Request Timeout` if the handler takes longer than `timeout`, use
+    /// [`MethodRouter::layer`] with a [`TimeoutLayer`] to change the response
+    pub fn timeout(self, timeout: Duration) -> Self {
+        return self.layer(TimeoutLayer::new(timeout));
+    }
+
+    /// lets the handler run at most `max` times at once, further requests wait for a slot. use
+    /// [`MethodRouter::layer`] with a [`ConcurrencyLimitLayer`] to export its stats
+    pub fn concurrency_limit(self, max: usize) -> Self {
+        return self.layer(ConcurrencyLimitLayer::new(max));
+    }
+
+    /// lets the handler run at most `max` times at once, further requests get
+    /// `503 Service Unavailable` right away
+    pub fn load_shed(self, max: usize) -> Self {
+        return self.layer(ConcurrencyLimitLayer::new(max).load_shed());
+    }
+}
+
+impl From<(Route, http::Method)> for MethodRouter {
+    fn from((route, method): (Route, http::Method)) -> Self {
+        return Self { route, method };
+    }
+}
+
 macro_rules! impl_method_router_methods {
     ($name:ident,$upper:ident) => {
-        pub fn $name<T, U>(handler: T) -> (Route, http::Method)
+        pub fn $name<T, U>(handler: T) -> MethodRouter
         where
             T: IntoHandler<U> + 'static,
             U: 'static,
         {
             let route = Arc::new(handler.into_handler());
-            return (Route(route), hyper::http::Method::$upper);
+            return MethodRouter {
+                route: Route(route),
+                method: hyper::http::Method::$upper,
+            };
         }
     };
 }
@@ -4,13 +4,24 @@ pub mod method_router;
 use std::fmt;
 use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc};
 
-use self::method_router::Route;
-use crate::http::{request::Request, response::Response};
-use crate::middleware::Middleware;
+use self::method_router::{MethodRouter, Route};
+use crate::http::{
+    body::{BoxBody, BoxError},
+    request::{FromRequestParts, Rejection, Request},
+    response::Response,
+};
+use crate::middleware::{
+    catch_panic::default_panic_response, handle_error::HandleErrorLayer, Middleware,
+};
 use anyhow::Result;
+use futures_util::FutureExt;
 use http_body_util::BodyExt;
-use hyper::{body::Incoming, http, StatusCode};
-use tower::layer::util::Identity;
+use hyper::{
+    body::{Body, Incoming},
+    http::{self, request::Parts},
+    StatusCode,
+};
+use tower::{layer::util::Identity, Layer};
 
 #[derive(Clone)]
 pub struct Router {
@@ -24,11 +35,33 @@ impl Router {
         };
     }
 
-    pub fn route(mut self, path: &str, route: (Route, http::Method)) -> Self {
+    pub fn route(mut self, path: &str, route: impl Into<MethodRouter>) -> Self {
         Arc::make_mut(&mut self.router).route(path, route);
         return self;
     }
 
+    /// wraps every route registered so far in `layer`.
+    ///
+    /// unlike [`Router::layer`] the result is still a `Router`, and requests which don't match
+    /// any route never reach the layer, so e.g. authentication doesn't turn a 404 into a 401.
+    ///
+    /// every route gets a service of its own from `layer`, but anything the layer shares between
+    /// its services is shared by all routes. a [`ConcurrencyLimitLayer`] limits the requests to
+    /// all routes together, use [`MethodRouter::concurrency_limit`] for a limit per route
+    ///
+    /// [`ConcurrencyLimitLayer`]: crate::middleware::concurrency_limit::ConcurrencyLimitLayer
+    /// [`MethodRouter::concurrency_limit`]: crate::router::method_router::MethodRouter::concurrency_limit
+    pub fn route_layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route>,
+        L::Service: tower::Service<Request, Response = Response> + Clone + Send + Sync + 'static,
+        <L::Service as tower::Service<Request>>::Future: Send,
+        <L::Service as tower::Service<Request>>::Error: Into<BoxError>,
+    {
+        Arc::make_mut(&mut self.router).route_layer(layer);
+        return self;
+    }
+
     pub fn layer<L>(self, layer: L) -> Middleware<L> {
         Middleware {
             layer,
@@ -36,6 +69,11 @@ impl Router {
         }
     }
 
+    /// turns errors of the router into responses, see [`HandleErrorLayer`]
+    pub fn handle_error<F>(self, f: F) -> Middleware<HandleErrorLayer<F>> {
+        return self.layer(HandleErrorLayer::new(f));
+    }
+
     pub fn middleware(self) -> Middleware<Identity> {
         return Middleware::new(self);
     }
@@ -52,15 +90,24 @@ impl Default for Router {
     }
 }
 
-async fn incoming_to_string(req: Request<Incoming>) -> Result<Request> {
+async fn incoming_to_string<B>(req: Request<B>) -> Result<Request>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
     let (parts, body) = req.into_parts();
-    let bytes = body.collect().await?.to_bytes().to_vec();
+    let bytes = body
+        .collect()
+        .await
+        .map_err(|err| anyhow::anyhow!(err.into()))?
+        .to_bytes()
+        .to_vec();
     let body = String::from_utf8(bytes)?;
     let req = Request::from_parts(parts, body);
     return Ok(req);
 }
 
-impl tower::Service<Request<Incoming>> for Router {
+impl tower::Service<Request<BoxBody>> for Router {
     type Response = Response<String>;
     type Error = anyhow::Error;
     type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
@@ -72,7 +119,7 @@ impl tower::Service<Request<Incoming>> for Router {
         std::task::Poll::Ready(Ok(()))
     }
 
-    fn call(&mut self, request: Request<Incoming>) -> Self::Future {
+    fn call(&mut self, request: Request<BoxBody>) -> Self::Future {
         let router = self.router.clone();
         return Box::pin(async move {
             let request = incoming_to_string(request).await?;
@@ -104,6 +151,48 @@ impl fmt::Display for Router {
     }
 }
 
+/// the path of the route a request matched, as it was registered.
+///
+/// it's inserted into the extensions of both the request and the response, so layers can use it
+/// e.g. to label metrics without a label per raw path
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatchedPath(Arc<str>);
+
+impl MatchedPath {
+    pub(crate) fn new(path: &str) -> Self {
+        return Self(Arc::from(path));
+    }
+
+    pub fn as_str(&self) -> &str {
+        return &self.0;
+    }
+}
+
+impl FromRequestParts for MatchedPath {
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        return parts
+            .extensions
+            .get::<MatchedPath>()
+            .cloned()
+            .ok_or_else(|| {
+                Rejection::new(StatusCode::BAD_REQUEST, "request did not match a route")
+                    .missing()
+                    .into()
+            });
+    }
+}
+
+/// the message of a panic payload, which is usually a `&str` or a `String`
+pub(crate) fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return message;
+    }
+    if let Some(message) = payload.downcast_ref::<String>() {
+        return message;
+    }
+    return "unknown panic payload";
+}
+
 type RoutePath = (http::Method, String);
 
 #[derive(Clone)]
@@ -118,17 +207,47 @@ impl InnerRouter {
         };
     }
 
-    pub fn route(&mut self, path: &str, route: (Route, http::Method)) -> () {
-        self.routes.insert((route.1, path.to_string()), route.0);
+    pub fn route(&mut self, path: &str, route: impl Into<MethodRouter>) -> () {
+        let route = route.into();
+        self.routes.insert((route.method, path.to_string()), route.route);
     }
 
-    pub(crate) async fn handle_request(&self, request: Request) -> Result<Response> {
-        let Some(route) = self
-            .routes
-            .get(&(request.method().clone(), request.uri().path().to_string())) else {
+    pub fn route_layer<L>(&mut self, layer: L) -> ()
+    where
+        L: Layer<Route>,
+        L::Service: tower::Service<Request, Response = Response> + Clone + Send + Sync + 'static,
+        <L::Service as tower::Service<Request>>::Future: Send,
+        <L::Service as tower::Service<Request>>::Error: Into<BoxError>,
+    {
+        for route in self.routes.values_mut() {
+            *route = route.clone().layer(&layer);
+        }
+    }
+
+    pub(crate) async fn handle_request(&self, mut request: Request) -> Result<Response> {
+        let key = (request.method().clone(), request.uri().path().to_string());
+        let Some(route) = self.routes.get(&key) else {
                 return Ok(hyper::Response::builder().status(StatusCode::NOT_FOUND).body(String::new())?);
             };
-        let res = route.0.call(request).await;
+        let matched = MatchedPath::new(&key.1);
+        request.extensions_mut().insert(matched.clone());
+
+        let mut res = match std::panic::AssertUnwindSafe(route.0.call(request))
+            .catch_unwind()
+            .await
+        {
+            Ok(res) => res,
+            // a panicking handler only fails its own request, not the whole connection
+            Err(payload) => {
+                tracing::error!(
+                    route = matched.as_str(),
+                    panic = panic_message(payload.as_ref()),
+                    "handler panicked"
+                );
+                default_panic_response()
+            }
+        };
+        res.extensions_mut().insert(matched);
 
         return Ok(res);
     }
@@ -0,0 +1,211 @@
+//! systemd style socket activation.
+//!
+//! a supervisor opens the listening sockets and passes them to the server starting at fd 3,
+//! announcing them through `LISTEN_PID`, `LISTEN_FDS` and `LISTEN_FDNAMES`. the same protocol is
+//! used by [`hand_off`] to pass the sockets of a running server on to its replacement.
+use anyhow::{bail, Context, Result};
+use socket2::Socket;
+use std::{
+    env, io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    os::unix::process::CommandExt,
+    process::{Child, Command},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use tokio::net::{TcpListener, UnixListener};
+
+use crate::server::listener::Listener;
+
+const LISTEN_FDS_START: RawFd = 3;
+
+/// set once the passed fds were taken, so they never end up owned by two listeners
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+impl Listener {
+    /// takes the listeners passed to this process by socket activation.
+    ///
+    /// returns an empty list if the process wasn't socket activated, or when called again after
+    /// the listeners were already taken.
+    ///
+    /// the environment variables are left alone, as changing the environment while other threads
+    /// may read it isn't safe. they don't affect child processes, which are told apart by
+    /// `LISTEN_PID`, and the fds are made close-on-exec so they don't leak into them
+    pub fn from_env() -> Result<Vec<Listener>> {
+        let Ok(pid) = env::var("LISTEN_PID") else {
+            return Ok(Vec::new());
+        };
+        let count = env::var("LISTEN_FDS").unwrap_or_default();
+        let names = env::var("LISTEN_FDNAMES").ok();
+
+        if pid.parse::<u32>().context("invalid LISTEN_PID")? != std::process::id() {
+            return Ok(Vec::new());
+        }
+        if TAKEN.swap(true, Ordering::SeqCst) {
+            return Ok(Vec::new());
+        }
+        let count = count.parse::<RawFd>().context("invalid LISTEN_FDS")?;
+        let mut names = names.as_deref().unwrap_or_default().split(':');
+
+        let mut listeners = Vec::with_capacity(count as usize);
+        for fd in LISTEN_FDS_START..LISTEN_FDS_START + count {
+            let mut listener = unsafe { listener_from_fd(fd)? };
+            listener.name = names
+                .next()
+                .filter(|name| !name.is_empty())
+                .map(str::to_string);
+            listeners.push(listener);
+        }
+        return Ok(listeners);
+    }
+}
+
+/// # Safety
+/// `fd` has to be an open socket owned by nobody else
+unsafe fn listener_from_fd(fd: RawFd) -> Result<Listener> {
+    // inherited fds don't have close-on-exec set, make sure they don't leak into other children
+    if libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) == -1 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("fd {} passed by LISTEN_FDS is not open", fd));
+    }
+    let socket = Socket::from_raw_fd(fd);
+    socket.set_nonblocking(true)?;
+    let addr = socket.local_addr()?;
+    if addr.as_socket().is_some() {
+        return Ok(TcpListener::from_std(socket.into())?.into());
+    }
+    if addr.is_unix() {
+        return Ok(UnixListener::from_std(socket.into())?.into());
+    }
+    bail!(
+        "fd {} passed by LISTEN_FDS is neither a tcp nor a unix socket",
+        fd
+    );
+}
+
+/// spawns `command` with `listeners` passed to it, to be picked up with [`Listener::from_env`].
+///
+/// this is meant for zero downtime restarts: spawn the new version of the server, and once it
+/// is up, stop the old one with a graceful shutdown. the listeners keep accepting connections
+/// in both processes until then.
+///
+/// the program, arguments, environment and working directory of `command` are respected, but
+/// `env_clear` and stdio settings are not. the program is started through `/bin/sh`, which
+/// replaces itself with it, as that is the only way to set `LISTEN_PID` to the pid of the child
+pub fn hand_off(listeners: &[&Listener], command: Command) -> Result<Child> {
+    let count = listeners.len() as RawFd;
+    // duplicates above the fds they are moved to in the child, so moving one into place can't
+    // clobber another one. they are close-on-exec, only the moved fds are inherited
+    let fds = listeners
+        .iter()
+        .map(|listener| {
+            let fd = unsafe {
+                libc::fcntl(
+                    listener.as_raw_fd(),
+                    libc::F_DUPFD_CLOEXEC,
+                    LISTEN_FDS_START + count,
+                )
+            };
+            if fd == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            return Ok(unsafe { OwnedFd::from_raw_fd(fd) });
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let names = listeners
+        .iter()
+        .map(|listener| listener.name().unwrap_or("unknown"))
+        .collect::<Vec<_>>()
+        .join(":");
+
+    let mut child = Command::new("/bin/sh");
+    child
+        .arg("-c")
+        .arg("LISTEN_PID=$$; export LISTEN_PID; exec \"$0\" \"$@\"")
+        .arg(command.get_program())
+        .args(command.get_args());
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => child.env(key, value),
+            None => child.env_remove(key),
+        };
+    }
+    if let Some(dir) = command.get_current_dir() {
+        child.current_dir(dir);
+    }
+    child
+        .env("LISTEN_FDS", fds.len().to_string())
+        .env("LISTEN_FDNAMES", names);
+
+    let raw_fds = fds.iter().map(AsRawFd::as_raw_fd).collect::<Vec<_>>();
+    // runs between fork and exec, so it must not allocate
+    unsafe {
+        child.pre_exec(move || {
+            for (i, fd) in raw_fds.iter().enumerate() {
+                // dup2 clears close-on-exec on the new fd
+                if libc::dup2(*fd, LISTEN_FDS_START + i as RawFd) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    let child = child.spawn()?;
+    drop(fds);
+    return Ok(child);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    const CHILD: &str = "AXTEL_ACTIVATION_CHILD";
+
+    /// the process spawned by `hand_off_passes_listeners`, which sends each listener's port to
+    /// the first connection accepted on it
+    #[tokio::test]
+    async fn child() {
+        if env::var_os(CHILD).is_none() {
+            return;
+        }
+        let listeners = Listener::from_env().unwrap();
+        assert_eq!(listeners.len(), 2);
+        assert_eq!(listeners[0].name(), Some("first"));
+        assert_eq!(listeners[1].name(), Some("unknown"));
+        // the fds are owned by the listeners above now
+        assert!(Listener::from_env().unwrap().is_empty());
+        for listener in &listeners {
+            let port = listener.local_addr().unwrap().port();
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(port.to_string().as_bytes()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn hand_off_passes_listeners() {
+        let mut first = Listener::from(TcpListener::bind("127.0.0.1:0").await.unwrap());
+        first.name = Some("first".to_string());
+        let second = Listener::from(TcpListener::bind("127.0.0.1:0").await.unwrap());
+
+        let mut command = Command::new(env::current_exe().unwrap());
+        command
+            .args(["server::activation::tests::child", "--exact", "-q"])
+            .env(CHILD, "1");
+        let mut child = hand_off(&[&first, &second], command).unwrap();
+
+        // nothing accepts in this process, so the child has to answer
+        for listener in [&first, &second] {
+            let addr = listener.local_addr().unwrap();
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let mut port = String::new();
+            stream.read_to_string(&mut port).await.unwrap();
+            assert_eq!(port, addr.port().to_string());
+        }
+        let status = tokio::task::spawn_blocking(move || child.wait())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(status.success());
+    }
+}
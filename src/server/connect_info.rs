@@ -0,0 +1,26 @@
+use anyhow::{anyhow, Result};
+use hyper::http::request::Parts;
+use std::net::SocketAddr;
+
+use crate::http::request::FromRequestParts;
+
+/// information about the connection a request arrived on.
+///
+/// when [PROXY protocol](crate::server::serve::Serve::proxy_protocol) is enabled, the addresses
+/// are the ones announced by the proxy and `proxy_addr` is the peer of the tcp connection
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConnectInfo {
+    pub remote_addr: SocketAddr,
+    pub local_addr: SocketAddr,
+    pub proxy_addr: Option<SocketAddr>,
+}
+
+impl FromRequestParts for ConnectInfo {
+    fn from_request_parts(parts: &Parts) -> Result<Self> {
+        return parts
+            .extensions
+            .get::<ConnectInfo>()
+            .copied()
+            .ok_or_else(|| anyhow!("missing connection info, was the request served by axtel?"));
+    }
+}
@@ -0,0 +1,404 @@
+use anyhow::{anyhow, Result};
+use h3::error::StreamError;
+use h3::server::{RequestResolver, RequestStream};
+use http_body_util::BodyExt;
+use hyper::body::{Body, Buf, Bytes, Frame};
+use hyper::http::HeaderValue;
+use quinn::crypto::rustls::QuicServerConfig;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tokio::sync::{mpsc, watch};
+use tower::{Service, ServiceExt};
+use tracing::Instrument;
+
+use crate::http::body::{self, BoxBody, BoxError};
+use crate::http::request::Request;
+use crate::http::response::Response;
+use crate::server::connect_info::ConnectInfo;
+use crate::server::serve::{ArcWrapper, Shared};
+use crate::server::stats::AcceptorStats;
+use crate::server::tls::TlsConfig;
+
+/// binds a quic endpoint for serving http/3
+pub(crate) fn bind(addr: SocketAddr, tls: &TlsConfig) -> Result<quinn::Endpoint> {
+    let mut config = (*tls.0).clone();
+    config.alpn_protocols = vec![b"h3".to_vec()];
+    let config = QuicServerConfig::try_from(config)
+        .map_err(|err| anyhow!("tls config can't be used for http/3: {}", err))?;
+    let config = quinn::ServerConfig::with_crypto(Arc::new(config));
+    return Ok(quinn::Endpoint::server(config, addr)?);
+}
+
+/// the `Alt-Svc` header advertising http/3 on the port of `endpoint` to tcp clients
+pub(crate) fn alt_svc(endpoint: &quinn::Endpoint) -> Result<HeaderValue> {
+    let port = endpoint.local_addr()?.port();
+    return Ok(HeaderValue::from_str(&format!(
+        "h3=\":{}\"; ma=86400",
+        port
+    ))?);
+}
+
+/// accepts quic connections until shutdown, and then waits for the accepted connections to close
+pub(crate) async fn accept_loop<S, B>(
+    endpoint: quinn::Endpoint,
+    stats: Arc<AcceptorStats>,
+    shared: Arc<Shared<S>>,
+    mut shutdown: watch::Receiver<()>,
+) -> Result<()>
+where
+    S: Service<Request<BoxBody>, Response = Response<B>> + Send + 'static,
+    S::Future: 'static + Send,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    let local_addr = endpoint.local_addr()?;
+    let (open_tx, mut open_rx) = mpsc::channel::<()>(1);
+    loop {
+        let permit = match &shared.limit {
+            Some(limit) => tokio::select! {
+                permit = limit.clone().acquire_owned() => Some(permit?),
+                _ = shutdown.changed() => break,
+            },
+            None => None,
+        };
+
+        let incoming = tokio::select! {
+            incoming = endpoint.accept() => incoming,
+            _ = shutdown.changed() => break,
+        };
+        // the endpoint was closed
+        let Some(incoming) = incoming else {
+            break;
+        };
+        stats.connection_opened();
+
+        let stats = stats.clone();
+        let open = open_tx.clone();
+        let service = shared.service.clone();
+        let shutdown = shutdown.clone();
+        let span = tracing::info_span!(
+            "connection",
+            protocol = "h3",
+            remote_addr = %incoming.remote_address(),
+            local_addr = %local_addr,
+        );
+        let connection = async move {
+            tracing::debug!("accepted connection");
+            match serve_connection(incoming, local_addr, service, shutdown).await {
+                Ok(()) => tracing::debug!("connection closed"),
+                Err(err) => tracing::warn!(error = %err, "connection closed with an error"),
+            }
+            stats.connection_closed();
+            drop(permit);
+            drop(open);
+        };
+        tokio::spawn(connection.instrument(span));
+    }
+
+    drop(open_tx);
+    open_rx.recv().await;
+    endpoint.wait_idle().await;
+    return Ok(());
+}
+
+async fn serve_connection<S, B>(
+    incoming: quinn::Incoming,
+    local_addr: SocketAddr,
+    service: ArcWrapper<S>,
+    mut shutdown: watch::Receiver<()>,
+) -> Result<()>
+where
+    S: Service<Request<BoxBody>, Response = Response<B>> + Send + 'static,
+    S::Future: 'static + Send,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    let conn = incoming.await?;
+    let info = ConnectInfo {
+        remote_addr: conn.remote_address(),
+        local_addr,
+        proxy_addr: None,
+    };
+    let mut conn = h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(conn)).await?;
+
+    let mut shutting_down = false;
+    loop {
+        let resolver = tokio::select! {
+            resolver = conn.accept() => resolver,
+            _ = shutdown.changed(), if !shutting_down => {
+                // lets the requests already in flight finish, the next accept returns `None`
+                // once they are done
+                conn.shutdown(0).await?;
+                shutting_down = true;
+                continue;
+            }
+        };
+        let resolver = match resolver {
+            Ok(Some(resolver)) => resolver,
+            Ok(None) => return Ok(()),
+            // the client closed the connection normally
+            Err(err) if err.is_h3_no_error() => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let service = service.clone();
+        let request = async move {
+            match serve_request(resolver, service, info).await {
+                Ok(()) => (),
+                Err(err) => tracing::warn!(error = %err, "failed to serve request"),
+            }
+        };
+        tokio::spawn(request.in_current_span());
+    }
+}
+
+/// converts an h3 request into the same [`Request`] tcp connections produce, and sends the
+/// response of the service back
+async fn serve_request<S, B>(
+    resolver: RequestResolver<h3_quinn::Connection, Bytes>,
+    service: ArcWrapper<S>,
+    info: ConnectInfo,
+) -> Result<()>
+where
+    S: Service<Request<BoxBody>, Response = Response<B>> + Send + 'static,
+    S::Future: 'static + Send,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    let (req, stream) = resolver.resolve_request().await?;
+    let (mut stream, recv) = stream.split();
+
+    let mut req = req.map(|()| {
+        body::boxed(RequestBody {
+            stream: recv,
+            data_done: false,
+            done: false,
+        })
+    });
+    req.extensions_mut().insert(info);
+
+    let res = match service.oneshot(req).await {
+        Ok(res) => res,
+        Err(err) => {
+            stream.stop_stream(h3::error::Code::H3_INTERNAL_ERROR);
+            return Err(anyhow!(err.into()));
+        }
+    };
+
+    let (parts, body) = res.into_parts();
+    stream
+        .send_response(Response::from_parts(parts, ()))
+        .await?;
+    tokio::pin!(body);
+    loop {
+        let frame = body.frame().await.transpose();
+        let Some(frame) = frame.map_err(|err| anyhow!(err.into()))? else {
+            break;
+        };
+        match frame.into_data() {
+            Ok(data) if data.is_empty() => (),
+            Ok(data) => stream.send_data(data).await?,
+            Err(frame) => {
+                if let Ok(trailers) = frame.into_trailers() {
+                    stream.send_trailers(trailers).await?;
+                    return Ok(());
+                }
+            }
+        }
+    }
+    stream.finish().await?;
+    return Ok(());
+}
+
+/// the body of an h3 request, streamed like the bodies of requests on tcp connections
+struct RequestBody {
+    stream: RequestStream<h3_quinn::RecvStream, Bytes>,
+    data_done: bool,
+    done: bool,
+}
+
+impl Body for RequestBody {
+    type Data = Bytes;
+    type Error = StreamError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        if !self.data_done {
+            match ready!(self.stream.poll_recv_data(cx)) {
+                Ok(Some(mut chunk)) => {
+                    let data = chunk.copy_to_bytes(chunk.remaining());
+                    return Poll::Ready(Some(Ok(Frame::data(data))));
+                }
+                Ok(None) => self.data_done = true,
+                Err(err) => {
+                    self.done = true;
+                    return Poll::Ready(Some(Err(err)));
+                }
+            }
+        }
+        let trailers = ready!(self.stream.poll_recv_trailers(cx));
+        self.done = true;
+        return Poll::Ready(
+            trailers
+                .map(|trailers| trailers.map(Frame::trailers))
+                .transpose(),
+        );
+    }
+
+    fn is_end_stream(&self) -> bool {
+        return self.done;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::{method_router::post, Router};
+    use crate::server::serve::Serve;
+    use quinn::crypto::rustls::QuicClientConfig;
+    use std::future::poll_fn;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tokio_rustls::rustls;
+
+    const CERT: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/server/testdata/localhost.crt"
+    );
+    const KEY: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/server/testdata/localhost.key"
+    );
+
+    type SendRequest = h3::client::SendRequest<h3_quinn::OpenStreams, Bytes>;
+
+    /// serves `service` over http/3 on loopback until the test ends
+    fn serve<S>(service: S) -> SocketAddr
+    where
+        S: Service<Request<BoxBody>, Response = Response> + Send + Sync + 'static,
+        S::Future: Send,
+        S::Error: Into<Box<dyn Error + Send + Sync>>,
+    {
+        // quinn binds the udp socket itself, so find a free port first
+        let addr = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let tls = TlsConfig::from_pem_files(CERT, KEY).unwrap();
+        // `Serve` has to stay on the thread it was created on
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(async move {
+                let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                Serve::new(tcp, service).http3(addr, tls).await.unwrap();
+            });
+        });
+        return addr;
+    }
+
+    async fn connect(addr: SocketAddr) -> SendRequest {
+        let cert = std::fs::read(CERT).unwrap();
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut cert.as_slice()) {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let mut tls = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        tls.alpn_protocols = vec![b"h3".to_vec()];
+        let config = QuicClientConfig::try_from(tls).unwrap();
+
+        let mut endpoint = quinn::Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+        endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(config)));
+        // the server may not be listening yet
+        let mut attempts = 0;
+        let conn = loop {
+            match endpoint.connect(addr, "localhost").unwrap().await {
+                Ok(conn) => break conn,
+                Err(_) if attempts < 50 => attempts += 1,
+                Err(err) => panic!("failed to connect: {}", err),
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        };
+        let (mut driver, send_request) = h3::client::new(h3_quinn::Connection::new(conn))
+            .await
+            .unwrap();
+        tokio::spawn(async move { poll_fn(|cx| driver.poll_close(cx)).await });
+        return send_request;
+    }
+
+    async fn read_body<S>(stream: &mut h3::client::RequestStream<S, Bytes>) -> Vec<u8>
+    where
+        S: h3::quic::RecvStream,
+    {
+        let mut body = Vec::new();
+        while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+            body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+        }
+        return body;
+    }
+
+    #[tokio::test]
+    async fn request_body() {
+        async fn echo(body: String) -> String {
+            return body;
+        }
+        let router = Router::new().route("/echo", post(echo));
+        let mut client = connect(serve(router)).await;
+
+        let req = hyper::Request::post("https://localhost/echo")
+            .body(())
+            .unwrap();
+        let mut stream = client.send_request(req).await.unwrap();
+        let chunk = "a".repeat(64 * 1024);
+        for _ in 0..16 {
+            stream.send_data(Bytes::from(chunk.clone())).await.unwrap();
+        }
+        stream.finish().await.unwrap();
+
+        let res = stream.recv_response().await.unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(read_body(&mut stream).await, chunk.repeat(16).into_bytes());
+    }
+
+    #[tokio::test]
+    async fn request_body_is_streamed() {
+        // answers with the first chunk of the body, before the client has sent the rest
+        let service = tower::service_fn(|req: Request<BoxBody>| async move {
+            let frame = req.into_body().frame().await.unwrap()?;
+            let data = frame.into_data().unwrap_or_default();
+            let res = Response::new(String::from_utf8(data.to_vec()).unwrap());
+            return Ok::<_, BoxError>(res);
+        });
+        let mut client = connect(serve(service)).await;
+
+        let req = hyper::Request::post("https://localhost/").body(()).unwrap();
+        let mut stream = client.send_request(req).await.unwrap();
+        stream.send_data(Bytes::from("first")).await.unwrap();
+
+        let res = tokio::time::timeout(Duration::from_secs(5), stream.recv_response())
+            .await
+            .expect("the response waited for the whole body")
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(read_body(&mut stream).await, b"first");
+    }
+}
@@ -0,0 +1,135 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Instant;
+
+/// wraps an io object and records when it was last read from or written to
+pub(crate) struct IdleIo<I> {
+    io: I,
+    activity: Activity,
+}
+
+impl<I> IdleIo<I> {
+    pub(crate) fn new(io: I) -> (Self, Activity) {
+        let activity = Activity {
+            start: Instant::now(),
+            last: Arc::new(AtomicU64::new(0)),
+            requests: Arc::new(AtomicUsize::new(0)),
+        };
+        let io = IdleIo {
+            io,
+            activity: activity.clone(),
+        };
+        return (io, activity);
+    }
+}
+
+/// shared handle to the last activity of an [`IdleIo`]
+#[derive(Clone)]
+pub(crate) struct Activity {
+    start: Instant,
+    // milliseconds since `start`
+    last: Arc<AtomicU64>,
+    // requests whose response hasn't been produced yet
+    requests: Arc<AtomicUsize>,
+}
+
+impl Activity {
+    fn touch(&self) {
+        let elapsed = self.start.elapsed().as_millis() as u64;
+        self.last.store(elapsed, Ordering::Relaxed);
+    }
+
+    /// marks a request as in progress until the returned guard is dropped, the connection doesn't
+    /// count as idle meanwhile even if no io happens
+    pub(crate) fn request(&self) -> RequestGuard {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        return RequestGuard(self.clone());
+    }
+
+    /// the point in time at which the connection counts as idle for `timeout`
+    pub(crate) fn deadline(&self, timeout: Duration) -> Instant {
+        if self.requests.load(Ordering::Relaxed) > 0 {
+            // checked again once the timeout passed, the guard moves the deadline when dropped
+            return Instant::now() + timeout;
+        }
+        let last = Duration::from_millis(self.last.load(Ordering::Relaxed));
+        return self.start + last + timeout;
+    }
+}
+
+pub(crate) struct RequestGuard(Activity);
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        self.0.touch();
+        self.0.requests.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<I> AsyncRead for IdleIo<I>
+where
+    I: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let res = Pin::new(&mut self.io).poll_read(cx, buf);
+        if buf.filled().len() != filled {
+            self.activity.touch();
+        }
+        res
+    }
+}
+
+impl<I> AsyncWrite for IdleIo<I>
+where
+    I: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let res = Pin::new(&mut self.io).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = res {
+            if n > 0 {
+                self.activity.touch();
+            }
+        }
+        res
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let res = Pin::new(&mut self.io).poll_write_vectored(cx, bufs);
+        if let Poll::Ready(Ok(n)) = res {
+            if n > 0 {
+                self.activity.touch();
+            }
+        }
+        res
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,252 @@
+#[cfg(unix)]
+use socket2::{Domain, Socket, Type};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+
+#[cfg(feature = "tls")]
+use crate::server::tls::TlsConfig;
+
+/// the http versions spoken on a [`Listener`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Protocol {
+    /// detect http/1 and http/2 per connection
+    #[default]
+    Auto,
+    Http1,
+    Http2,
+}
+
+/// a socket accepting connections for [`Serve`](crate::server::serve::Serve), together with the
+/// protocol settings used for the connections accepted on it
+pub struct Listener {
+    inner: Inner,
+    pub(crate) name: Option<String>,
+    pub(crate) protocol: Protocol,
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<TlsConfig>,
+}
+
+enum Inner {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    fn new(inner: Inner) -> Self {
+        return Self {
+            inner,
+            name: None,
+            protocol: Protocol::Auto,
+            #[cfg(feature = "tls")]
+            tls: None,
+        };
+    }
+
+    /// binds `count` tcp listeners to the same address with `SO_REUSEPORT`, letting the kernel
+    /// spread incoming connections across them. with port 0 they share the port picked for the
+    /// first one
+    ///
+    /// pass them to [`Serve::listeners`](crate::server::serve::Serve::listeners) to run an accept
+    /// loop for each of them
+    ///
+    /// # Panics
+    /// panics if called outside of a tokio runtime
+    #[cfg(unix)]
+    pub fn bind_reuse_port(mut addr: SocketAddr, count: usize) -> io::Result<Vec<Listener>> {
+        let mut listeners = Vec::with_capacity(count);
+        for _ in 0..count {
+            let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+            socket.set_reuse_address(true)?;
+            socket.set_reuse_port(true)?;
+            socket.set_nonblocking(true)?;
+            socket.bind(&addr.into())?;
+            socket.listen(1024)?;
+            let listener = TcpListener::from_std(socket.into())?;
+            addr = listener.local_addr()?;
+            listeners.push(listener.into());
+        }
+        return Ok(listeners);
+    }
+
+    /// moves the listener over to the runtime of the current thread
+    pub(crate) fn reregister(self) -> io::Result<Listener> {
+        let inner = match self.inner {
+            Inner::Tcp(listener) => Inner::Tcp(TcpListener::from_std(listener.into_std()?)?),
+            #[cfg(unix)]
+            Inner::Unix(listener) => Inner::Unix(UnixListener::from_std(listener.into_std()?)?),
+        };
+        return Ok(Listener { inner, ..self });
+    }
+
+    /// only speak the given http version on this listener
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        return self;
+    }
+
+    /// terminate tls on this listener
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, config: TlsConfig) -> Self {
+        self.tls = Some(config);
+        return self;
+    }
+
+    /// the name given to this listener by socket activation, see
+    /// [`Listener::from_env`](crate::server::activation)
+    pub fn name(&self) -> Option<&str> {
+        return self.name.as_deref();
+    }
+
+    /// the local address of a tcp listener
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match &self.inner {
+            Inner::Tcp(listener) => listener.local_addr().ok(),
+            #[cfg(unix)]
+            Inner::Unix(_) => None,
+        }
+    }
+
+    /// accepts a connection, returning the socket addresses of both ends for tcp connections
+    pub(crate) async fn accept(&self) -> io::Result<(Stream, Option<(SocketAddr, SocketAddr)>)> {
+        match &self.inner {
+            Inner::Tcp(listener) => {
+                let (stream, remote_addr) = listener.accept().await?;
+                let local_addr = stream.local_addr()?;
+                return Ok((Stream::Tcp(stream), Some((remote_addr, local_addr))));
+            }
+            #[cfg(unix)]
+            Inner::Unix(listener) => {
+                let (stream, _) = listener.accept().await?;
+                return Ok((Stream::Unix(stream), None));
+            }
+        }
+    }
+}
+
+impl From<TcpListener> for Listener {
+    fn from(listener: TcpListener) -> Self {
+        return Self::new(Inner::Tcp(listener));
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixListener> for Listener {
+    fn from(listener: UnixListener) -> Self {
+        return Self::new(Inner::Unix(listener));
+    }
+}
+
+#[cfg(unix)]
+impl std::os::fd::AsRawFd for Listener {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        match &self.inner {
+            Inner::Tcp(listener) => listener.as_raw_fd(),
+            Inner::Unix(listener) => listener.as_raw_fd(),
+        }
+    }
+}
+
+/// a connection accepted by a [`Listener`]
+pub(crate) enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Stream::Tcp(stream) => stream.is_write_vectored(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn reuse_port() {
+        let listeners = Listener::bind_reuse_port("127.0.0.1:0".parse().unwrap(), 2).unwrap();
+        let addr = listeners[0].local_addr().unwrap();
+        assert_ne!(addr.port(), 0);
+        assert_eq!(listeners[1].local_addr(), Some(addr));
+
+        // the kernel picks a listener by hashing the client address, so a few connections have
+        // to reach both
+        let mut accepted = [0; 2];
+        for _ in 0..32 {
+            let _client = TcpStream::connect(addr).await.unwrap();
+            let i = tokio::select! {
+                res = listeners[0].accept() => res.map(|_| 0),
+                res = listeners[1].accept() => res.map(|_| 1),
+            };
+            accepted[i.unwrap()] += 1;
+        }
+        assert!(accepted[0] > 0 && accepted[1] > 0, "{:?}", accepted);
+    }
+}
@@ -1,19 +1,37 @@
+#[cfg(unix)]
+pub mod activation;
+pub mod connect_info;
+#[cfg(feature = "http3")]
+mod http3;
+mod idle;
+pub mod listener;
+pub mod proxy;
 pub mod serve;
+pub mod stats;
+#[cfg(feature = "tls")]
+pub mod tls;
 
 use std::error::Error;
 
+use hyper::body::{Body, Bytes};
+
 use crate::{
-    http::{request::Request, response::Response},
-    server::serve::Serve,
+    http::{
+        body::{BoxBody, BoxError},
+        request::Request,
+        response::Response,
+    },
+    server::{listener::Listener, serve::Serve},
 };
-use hyper::{body::Incoming, service::Service};
-use tokio::net::TcpListener;
 
-pub fn serve<S>(listener: TcpListener, service: S) -> Serve<S>
+pub fn serve<L, S, B>(listener: L, service: S) -> Serve<S>
 where
-    S: tower::Service<Request<Incoming>, Response = Response> + Send + Sync + 'static,
+    L: Into<Listener>,
+    S: tower::Service<Request<BoxBody>, Response = Response<B>> + Send + Sync + 'static,
     S::Future: 'static + Send,
     S::Error: Into<Box<dyn Error + Send + Sync>>,
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
 {
     return Serve::new(listener, service);
 }
@@ -0,0 +1,303 @@
+use anyhow::{bail, Context, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const V1_PREFIX: &[u8; 6] = b"PROXY ";
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+/// the longest possible v1 header including the trailing CRLF
+const V1_MAX_LEN: usize = 107;
+
+/// the addresses announced by a PROXY protocol header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProxyAddrs {
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+}
+
+/// reads a PROXY protocol v1 or v2 header from the start of `io`.
+///
+/// exactly the bytes belonging to the header are consumed, so the stream can be handed to hyper
+/// afterwards. returns `None` for `UNKNOWN` (v1) and `LOCAL` (v2) headers, in which case the
+/// socket addresses should be used.
+pub(crate) async fn read_proxy_header<I>(io: &mut I) -> Result<Option<ProxyAddrs>>
+where
+    I: AsyncRead + Unpin,
+{
+    let mut prefix = [0u8; 6];
+    io.read_exact(&mut prefix)
+        .await
+        .context("failed to read PROXY header")?;
+
+    if &prefix == V1_PREFIX {
+        return read_v1(io).await;
+    }
+    if prefix == V2_SIGNATURE[..6] {
+        let mut rest = [0u8; 6];
+        io.read_exact(&mut rest).await?;
+        if rest == V2_SIGNATURE[6..] {
+            return read_v2(io).await;
+        }
+    }
+    bail!("connection did not start with a PROXY header");
+}
+
+async fn read_v1<I>(io: &mut I) -> Result<Option<ProxyAddrs>>
+where
+    I: AsyncRead + Unpin,
+{
+    // the header has no length field, so read byte by byte to avoid consuming request data
+    let mut line = Vec::with_capacity(V1_MAX_LEN - V1_PREFIX.len());
+    loop {
+        if V1_PREFIX.len() + line.len() >= V1_MAX_LEN {
+            bail!("PROXY v1 header is too long");
+        }
+        line.push(io.read_u8().await?);
+        if line.ends_with(b"\r\n") {
+            line.truncate(line.len() - 2);
+            break;
+        }
+    }
+
+    let line = std::str::from_utf8(&line).context("PROXY v1 header is not valid ascii")?;
+    let mut fields = line.split(' ');
+    let ipv6 = match fields.next() {
+        Some("TCP4") => false,
+        Some("TCP6") => true,
+        Some("UNKNOWN") => return Ok(None),
+        _ => bail!("unsupported PROXY v1 protocol: {}", line),
+    };
+
+    let (Some(src), Some(dst), Some(src_port), Some(dst_port), None) = (
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+    ) else {
+        bail!("malformed PROXY v1 header: {}", line);
+    };
+
+    let (src, dst) = (src.parse::<IpAddr>()?, dst.parse::<IpAddr>()?);
+    if src.is_ipv6() != ipv6 || dst.is_ipv6() != ipv6 {
+        bail!("PROXY v1 addresses don't match the protocol: {}", line);
+    }
+    let source = SocketAddr::new(src, src_port.parse()?);
+    let destination = SocketAddr::new(dst, dst_port.parse()?);
+    return Ok(Some(ProxyAddrs {
+        source,
+        destination,
+    }));
+}
+
+async fn read_v2<I>(io: &mut I) -> Result<Option<ProxyAddrs>>
+where
+    I: AsyncRead + Unpin,
+{
+    let version_command = io.read_u8().await?;
+    let family = io.read_u8().await?;
+    let len = io.read_u16().await? as usize;
+
+    if version_command >> 4 != 2 {
+        bail!(
+            "unsupported PROXY protocol version: {}",
+            version_command >> 4
+        );
+    }
+
+    // the address block is always read in full, including any TLVs we don't care about
+    let mut block = vec![0u8; len];
+    io.read_exact(&mut block).await?;
+
+    match version_command & 0x0f {
+        0x0 => return Ok(None),
+        0x1 => (),
+        cmd => bail!("unsupported PROXY v2 command: {}", cmd),
+    }
+
+    let port = |offset: usize| u16::from_be_bytes([block[offset], block[offset + 1]]);
+    match family >> 4 {
+        // AF_INET
+        0x1 => {
+            if block.len() < 12 {
+                bail!("PROXY v2 address block is too short");
+            }
+            let src = Ipv4Addr::from(<[u8; 4]>::try_from(&block[0..4])?);
+            let dst = Ipv4Addr::from(<[u8; 4]>::try_from(&block[4..8])?);
+            return Ok(Some(ProxyAddrs {
+                source: SocketAddr::new(src.into(), port(8)),
+                destination: SocketAddr::new(dst.into(), port(10)),
+            }));
+        }
+        // AF_INET6
+        0x2 => {
+            if block.len() < 36 {
+                bail!("PROXY v2 address block is too short");
+            }
+            let src = Ipv6Addr::from(<[u8; 16]>::try_from(&block[0..16])?);
+            let dst = Ipv6Addr::from(<[u8; 16]>::try_from(&block[16..32])?);
+            return Ok(Some(ProxyAddrs {
+                source: SocketAddr::new(src.into(), port(32)),
+                destination: SocketAddr::new(dst.into(), port(34)),
+            }));
+        }
+        // AF_UNSPEC and AF_UNIX carry no addresses we can represent
+        0x0 | 0x3 => return Ok(None),
+        family => bail!("unsupported PROXY v2 address family: {}", family),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v2(command: u8, family: u8, block: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x20 | command);
+        header.push(family);
+        header.extend_from_slice(&(block.len() as u16).to_be_bytes());
+        header.extend_from_slice(block);
+        return header;
+    }
+
+    async fn read(mut input: &[u8]) -> (Result<Option<ProxyAddrs>>, &[u8]) {
+        let res = read_proxy_header(&mut input).await;
+        return (res, input);
+    }
+
+    #[tokio::test]
+    async fn v1_tcp4() {
+        let (res, rest) = read(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET /").await;
+        let addrs = res.unwrap().unwrap();
+        assert_eq!(addrs.source, "192.0.2.1:56324".parse().unwrap());
+        assert_eq!(addrs.destination, "198.51.100.1:443".parse().unwrap());
+        assert_eq!(rest, b"GET /");
+    }
+
+    #[tokio::test]
+    async fn v1_tcp6() {
+        let (res, _) = read(b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\n").await;
+        let addrs = res.unwrap().unwrap();
+        assert_eq!(addrs.source, "[2001:db8::1]:56324".parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn v1_unknown() {
+        let (res, rest) = read(b"PROXY UNKNOWN\r\nGET /").await;
+        assert_eq!(res.unwrap(), None);
+        assert_eq!(rest, b"GET /");
+    }
+
+    #[tokio::test]
+    async fn v1_family_mismatch() {
+        let (res, _) = read(b"PROXY TCP4 2001:db8::1 2001:db8::2 56324 443\r\n").await;
+        assert!(res.is_err());
+        let (res, _) = read(b"PROXY TCP6 192.0.2.1 198.51.100.1 56324 443\r\n").await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn v1_malformed() {
+        for input in [
+            &b"PROXY TCP4 192.0.2.1 198.51.100.1 56324\r\n"[..],
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443 1\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 99999\r\n",
+            b"PROXY UDP4 192.0.2.1 198.51.100.1 56324 443\r\n",
+        ] {
+            assert!(read(input).await.0.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn v1_too_long() {
+        let mut input = b"PROXY TCP6 ".to_vec();
+        input.resize(V1_MAX_LEN + 10, b'a');
+        input.extend_from_slice(b"\r\n");
+        let (res, rest) = read(&input).await;
+        assert!(res.is_err());
+        // reading stops at the limit instead of searching for the end of the line
+        assert!(rest.len() > 10);
+    }
+
+    #[tokio::test]
+    async fn truncated() {
+        assert!(read(b"").await.0.is_err());
+        assert!(read(b"PRO").await.0.is_err());
+        assert!(read(b"PROXY TCP4 192.0.2.1").await.0.is_err());
+        assert!(read(&V2_SIGNATURE[..8]).await.0.is_err());
+
+        let mut header = v2(0x1, 0x11, &[0; 12]);
+        header.truncate(header.len() - 1);
+        assert!(read(&header).await.0.is_err());
+    }
+
+    #[tokio::test]
+    async fn not_proxy() {
+        assert!(read(b"GET / HTTP/1.1\r\n").await.0.is_err());
+        assert!(read(b"\r\n\r\n\0\r\nHELLO").await.0.is_err());
+    }
+
+    #[tokio::test]
+    async fn v2_proxy_inet() {
+        let mut block = vec![192, 0, 2, 1, 198, 51, 100, 1];
+        block.extend_from_slice(&56324u16.to_be_bytes());
+        block.extend_from_slice(&443u16.to_be_bytes());
+        // TLVs after the addresses are skipped
+        block.extend_from_slice(&[0x04, 0x00, 0x01, 0xff]);
+        let mut input = v2(0x1, 0x11, &block);
+        input.extend_from_slice(b"GET /");
+
+        let (res, rest) = read(&input).await;
+        let addrs = res.unwrap().unwrap();
+        assert_eq!(addrs.source, "192.0.2.1:56324".parse().unwrap());
+        assert_eq!(addrs.destination, "198.51.100.1:443".parse().unwrap());
+        assert_eq!(rest, b"GET /");
+    }
+
+    #[tokio::test]
+    async fn v2_proxy_inet6() {
+        let src: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let dst: Ipv6Addr = "2001:db8::2".parse().unwrap();
+        let mut block = src.octets().to_vec();
+        block.extend_from_slice(&dst.octets());
+        block.extend_from_slice(&56324u16.to_be_bytes());
+        block.extend_from_slice(&443u16.to_be_bytes());
+
+        let (res, _) = read(&v2(0x1, 0x21, &block)).await;
+        let addrs = res.unwrap().unwrap();
+        assert_eq!(addrs.source, SocketAddr::new(src.into(), 56324));
+        assert_eq!(addrs.destination, SocketAddr::new(dst.into(), 443));
+    }
+
+    #[tokio::test]
+    async fn v2_local() {
+        let mut input = v2(0x0, 0x11, &[1; 12]);
+        input.extend_from_slice(b"GET /");
+        let (res, rest) = read(&input).await;
+        assert_eq!(res.unwrap(), None);
+        assert_eq!(rest, b"GET /");
+    }
+
+    #[tokio::test]
+    async fn v2_short_block() {
+        assert!(read(&v2(0x1, 0x11, &[0; 11])).await.0.is_err());
+        assert!(read(&v2(0x1, 0x21, &[0; 35])).await.0.is_err());
+    }
+
+    #[tokio::test]
+    async fn v2_families() {
+        // AF_UNSPEC and AF_UNIX fall back to the socket addresses
+        assert_eq!(read(&v2(0x1, 0x00, &[])).await.0.unwrap(), None);
+        assert_eq!(read(&v2(0x1, 0x31, &[0; 216])).await.0.unwrap(), None);
+        assert!(read(&v2(0x1, 0x41, &[0; 12])).await.0.is_err());
+        assert!(read(&v2(0x1, 0xf1, &[0; 12])).await.0.is_err());
+    }
+
+    #[tokio::test]
+    async fn v2_unsupported() {
+        assert!(read(&v2(0x2, 0x11, &[0; 12])).await.0.is_err());
+
+        let mut input = v2(0x1, 0x11, &[0; 12]);
+        input[12] = 0x11;
+        assert!(read(&input).await.0.is_err());
+    }
+}
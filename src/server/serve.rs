@@ -1,70 +1,578 @@
+use crate::http::body::{self, BoxBody, BoxError};
 use crate::http::request::Request;
 use crate::http::response::Response;
-use anyhow::Result;
-use hyper::body::Incoming;
+use crate::server::connect_info::ConnectInfo;
+use crate::server::idle::{Activity, IdleIo};
+use crate::server::listener::{Listener, Protocol, Stream};
+use crate::server::proxy::read_proxy_header;
+use crate::server::stats::AcceptorStats;
+use anyhow::{bail, Context, Result};
+use hyper::body::{Body, Bytes, Incoming};
 use hyper_util::rt::{TokioExecutor, TokioIo};
 use hyper_util::server::conn::auto;
 use hyper_util::service::TowerToHyperService;
 use std::error::Error;
-use std::sync::{Arc, Mutex};
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
 use std::{
     future::{Future, IntoFuture},
     pin::Pin,
 };
-use tokio::net::TcpListener;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{mpsc, oneshot, watch, Semaphore};
+use tokio::task::JoinSet;
+use tokio::time::Instant;
 use tower::Service;
+use tracing::{field, Instrument, Span};
+
+#[cfg(feature = "http3")]
+use crate::server::http3;
+#[cfg(feature = "tls")]
+use crate::server::tls::TlsConfig;
+use hyper::http::HeaderValue;
+#[cfg(feature = "tls")]
+use tokio_rustls::TlsAcceptor;
+
+const ACCEPT_BACKOFF_MIN: Duration = Duration::from_millis(5);
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+#[cfg(feature = "tls")]
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+type CloneService<S> = fn(&S) -> S;
 
 pub struct Serve<S> {
-    listener: TcpListener,
+    listeners: Vec<(Listener, Arc<AcceptorStats>)>,
     service: S,
+    // whether to pin the acceptor threads to cores, and how to give each of them its own service
+    thread_per_acceptor: Option<(bool, CloneService<S>)>,
+    #[cfg(feature = "http3")]
+    http3: Option<(std::net::SocketAddr, TlsConfig, Arc<AcceptorStats>)>,
+    proxy_protocol: Option<Duration>,
+    max_connections: Option<usize>,
+    idle_timeout: Option<Duration>,
+    signal: Option<Pin<Box<dyn Future<Output = ()>>>>,
 }
 
 impl<S> Serve<S> {
-    pub fn new(listener: TcpListener, service: S) -> Self {
-        Self { listener, service }
+    pub fn new(listener: impl Into<Listener>, service: S) -> Self {
+        Self {
+            listeners: Vec::new(),
+            service,
+            thread_per_acceptor: None,
+            #[cfg(feature = "http3")]
+            http3: None,
+            proxy_protocol: None,
+            max_connections: None,
+            idle_timeout: None,
+            signal: None,
+        }
+        .listener(listener)
+    }
+
+    /// serve the same service on an additional listener.
+    ///
+    /// every listener keeps its own [`Protocol`] and tls settings, everything configured on
+    /// `Serve` applies to all of them
+    pub fn listener(mut self, listener: impl Into<Listener>) -> Self {
+        let listener = listener.into();
+        let stats = AcceptorStats::new(listener.local_addr(), listener.name.clone());
+        self.listeners.push((listener, Arc::new(stats)));
+        return self;
+    }
+
+    /// serve the same service on several additional listeners, see [`Serve::listener`]
+    pub fn listeners<L>(mut self, listeners: impl IntoIterator<Item = L>) -> Self
+    where
+        L: Into<Listener>,
+    {
+        for listener in listeners {
+            self = self.listener(listener);
+        }
+        return self;
+    }
+
+    /// run the accept loop of every listener on a thread of its own, each with a single
+    /// threaded runtime serving the connections accepted by it and a clone of the service.
+    ///
+    /// combined with [`Listener::bind_reuse_port`] this spreads connections across cores without
+    /// any work stealing. with `pin_to_core` the threads are pinned to a core each, round robin
+    pub fn thread_per_acceptor(mut self, pin_to_core: bool) -> Self
+    where
+        S: Clone,
+    {
+        self.thread_per_acceptor = Some((pin_to_core, S::clone));
+        return self;
+    }
+
+    /// additionally serve http/3 over quic on the udp port `addr`.
+    ///
+    /// responses on tcp connections advertise the http/3 endpoint with an `Alt-Svc` header, so
+    /// clients can switch over
+    #[cfg(feature = "http3")]
+    pub fn http3(mut self, addr: std::net::SocketAddr, tls: TlsConfig) -> Self {
+        let stats = Arc::new(AcceptorStats::new(Some(addr), None));
+        self.http3 = Some((addr, tls, stats));
+        return self;
+    }
+
+    /// the counters of every listener, in the order they were added, followed by the http/3
+    /// endpoint if there is one
+    pub fn stats(&self) -> Vec<Arc<AcceptorStats>> {
+        let stats = self.listeners.iter().map(|(_, stats)| stats.clone());
+        #[cfg(feature = "http3")]
+        let stats = stats.chain(self.http3.iter().map(|(_, _, stats)| stats.clone()));
+        return stats.collect();
+    }
+
+    /// expect every connection to start with a PROXY protocol v1 or v2 header.
+    ///
+    /// connections which don't send a valid header within `read_timeout` are closed. the
+    /// addresses from the header are available through [`ConnectInfo`]
+    pub fn proxy_protocol(mut self, read_timeout: Duration) -> Self {
+        self.proxy_protocol = Some(read_timeout);
+        return self;
+    }
+
+    /// limit the number of connections served at the same time.
+    ///
+    /// once the limit is reached no further connections are accepted until one of the open ones
+    /// is closed, leaving new clients in the listen backlog of the kernel
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        return self;
+    }
+
+    /// close connections which haven't read or written anything for `timeout`. connections with a
+    /// request still being handled don't count as idle.
+    ///
+    /// idle connections are first shut down gracefully, if that doesn't finish within another
+    /// `timeout` the connection is dropped
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        return self;
+    }
+
+    /// stop accepting connections once `signal` completes, and resolve after all open
+    /// connections have been shut down gracefully
+    pub fn with_graceful_shutdown<F>(mut self, signal: F) -> Self
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        self.signal = Some(Box::pin(signal));
+        return self;
     }
 }
 
-impl<S> IntoFuture for Serve<S>
+impl<S, B> IntoFuture for Serve<S>
 where
-    S: Service<Request<Incoming>, Response = Response> + Send + Sync + 'static,
+    S: Service<Request<BoxBody>, Response = Response<B>> + Send + Sync + 'static,
     S::Future: 'static + Send,
     S::Error: Into<Box<dyn Error + Send + Sync>>,
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
 {
     type Output = Result<()>;
     type IntoFuture = Pin<Box<dyn Future<Output = Self::Output>>>;
 
     fn into_future(self) -> Self::IntoFuture {
         return Box::pin(async move {
-            let service = TowerToHyperService::new(ArcWrapper::new(self.service));
-            loop {
-                let (stream, _) = self.listener.accept().await?;
-                let service = service.clone();
-                tokio::task::spawn(async move {
-                    match auto::Builder::new(TokioExecutor::new())
-                        .serve_connection(TokioIo::new(stream), service)
-                        .await
-                    {
-                        Ok(()) => (),
-                        Err(err) => {
-                            eprintln!("encounterd an error: {}", err);
+            #[cfg(feature = "http3")]
+            let http3 = match self.http3 {
+                Some((addr, tls, stats)) => Some((http3::bind(addr, &tls)?, stats)),
+                None => None,
+            };
+            let alt_svc = None;
+            #[cfg(feature = "http3")]
+            let alt_svc = match &http3 {
+                Some((endpoint, _)) => Some(http3::alt_svc(endpoint)?),
+                None => alt_svc,
+            };
+
+            let limit = self
+                .max_connections
+                .map(|max| Arc::new(Semaphore::new(max)));
+            let new_shared = |service| {
+                return Arc::new(Shared {
+                    service: ArcWrapper::new(service),
+                    limit: limit.clone(),
+                    proxy_protocol: self.proxy_protocol,
+                    idle_timeout: self.idle_timeout,
+                    alt_svc: alt_svc.clone(),
+                });
+            };
+
+            let (shutdown_tx, shutdown_rx) = watch::channel(());
+            let cores = core_affinity::get_core_ids().unwrap_or_default();
+            let mut acceptors = JoinSet::new();
+            let shared = new_shared(self.service);
+            for (i, (listener, stats)) in self.listeners.into_iter().enumerate() {
+                let shutdown = shutdown_rx.clone();
+                let Some((pin_to_core, clone)) = self.thread_per_acceptor else {
+                    acceptors.spawn(accept_loop(listener, stats, shared.clone(), shutdown));
+                    continue;
+                };
+                // every acceptor gets a service of its own, so they don't contend for its lock
+                let shared = new_shared(clone(&shared.service.lock()));
+
+                let core = cores
+                    .get(i % cores.len().max(1))
+                    .copied()
+                    .filter(|_| pin_to_core);
+                let (tx, rx) = oneshot::channel();
+                std::thread::Builder::new()
+                    .name(format!("axtel-acceptor-{}", i))
+                    .spawn(move || {
+                        if let Some(core) = core {
+                            core_affinity::set_for_current(core);
                         }
+                        let res = tokio::runtime::Builder::new_current_thread()
+                            .enable_all()
+                            .build()
+                            .map_err(anyhow::Error::from)
+                            .and_then(|runtime| {
+                                runtime.block_on(async move {
+                                    let listener = listener.reregister()?;
+                                    accept_loop(listener, stats, shared, shutdown).await
+                                })
+                            });
+                        let _ = tx.send(res);
+                    })?;
+                acceptors.spawn(async move { rx.await? });
+            }
+            #[cfg(feature = "http3")]
+            if let Some((endpoint, stats)) = http3 {
+                acceptors.spawn(http3::accept_loop(
+                    endpoint,
+                    stats,
+                    shared.clone(),
+                    shutdown_rx.clone(),
+                ));
+            }
+            drop(shutdown_rx);
+
+            match self.signal {
+                Some(signal) => {
+                    tokio::select! {
+                        _ = signal => (),
+                        Some(res) = acceptors.join_next() => res??,
                     }
-                })
-                .await?;
+                }
+                None => {
+                    while let Some(res) = acceptors.join_next().await {
+                        res??;
+                    }
+                }
+            }
+
+            // the acceptors only return once all of their connections are closed
+            shutdown_tx.send_replace(());
+            while let Some(res) = acceptors.join_next().await {
+                res??;
             }
+            return Ok(());
+        });
+    }
+}
+
+pub(crate) struct Shared<S> {
+    pub(crate) service: ArcWrapper<S>,
+    pub(crate) limit: Option<Arc<Semaphore>>,
+    proxy_protocol: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    alt_svc: Option<HeaderValue>,
+}
+
+/// accepts connections until shutdown, and then waits for the accepted connections to close
+async fn accept_loop<S, B>(
+    listener: Listener,
+    stats: Arc<AcceptorStats>,
+    shared: Arc<Shared<S>>,
+    mut shutdown: watch::Receiver<()>,
+) -> Result<()>
+where
+    S: Service<Request<BoxBody>, Response = Response<B>> + Send + 'static,
+    S::Future: 'static + Send,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    let builder = Arc::new(connection_builder(listener.protocol));
+    #[cfg(feature = "tls")]
+    let tls = listener
+        .tls
+        .as_ref()
+        .map(|tls| tls_acceptor(tls, listener.protocol));
+
+    // every connection holds a sender, so receiving `None` means all of them are closed
+    let (open_tx, mut open_rx) = mpsc::channel::<()>(1);
+    let mut backoff = ACCEPT_BACKOFF_MIN;
+    loop {
+        let permit = match &shared.limit {
+            Some(limit) => tokio::select! {
+                permit = limit.clone().acquire_owned() => Some(permit?),
+                _ = shutdown.changed() => break,
+            },
+            None => None,
+        };
+
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = shutdown.changed() => break,
+        };
+        let (stream, addrs) = match accepted {
+            Ok(accepted) => accepted,
+            Err(err) if is_connection_error(&err) => continue,
+            Err(err) => {
+                stats.accept_failed();
+                // most likely out of file descriptors, give open connections a chance to finish
+                // instead of spinning on the error
+                tracing::error!(error = %err, ?backoff, "failed to accept connection, retrying");
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(ACCEPT_BACKOFF_MAX);
+                continue;
+            }
+        };
+        backoff = ACCEPT_BACKOFF_MIN;
+        stats.connection_opened();
+
+        let stats = stats.clone();
+        let open = open_tx.clone();
+        let shared = shared.clone();
+        let builder = builder.clone();
+        let shutdown = shutdown.clone();
+        #[cfg(feature = "tls")]
+        let tls = tls.clone();
+        let span = tracing::info_span!(
+            "connection",
+            listener = listener.name(),
+            // the configured protocol, replaced by the http version of the first request
+            protocol = ?listener.protocol,
+            remote_addr = field::Empty,
+            local_addr = field::Empty,
+            alpn = field::Empty,
+        );
+        let connection = async move {
+            let res = async {
+                let (stream, info) = accept_connection(stream, addrs, &shared).await?;
+                if let Some(info) = info {
+                    let span = Span::current();
+                    span.record("remote_addr", field::display(info.remote_addr));
+                    span.record("local_addr", field::display(info.local_addr));
+                }
+                #[cfg(feature = "tls")]
+                if let Some(tls) = tls {
+                    let stream = tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, tls.accept(stream))
+                        .await
+                        .context("timed out during tls handshake")??;
+                    if let Some(alpn) = stream.get_ref().1.alpn_protocol() {
+                        Span::current().record("alpn", String::from_utf8_lossy(alpn).as_ref());
+                    }
+                    return serve_connection(stream, info, &shared, &builder, shutdown).await;
+                }
+                serve_connection(stream, info, &shared, &builder, shutdown).await
+            };
+            tracing::debug!("accepted connection");
+            match res.await {
+                Ok(()) => tracing::debug!("connection closed"),
+                Err(err) => tracing::warn!(error = %err, "connection closed with an error"),
+            }
+            stats.connection_closed();
+            drop(permit);
+            drop(open);
+        };
+        tokio::task::spawn(connection.instrument(span));
+    }
+
+    drop(listener);
+    drop(open_tx);
+    open_rx.recv().await;
+    return Ok(());
+}
+
+/// errors which only concern the connection being accepted, not the listener
+fn is_connection_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    )
+}
+
+fn connection_builder(protocol: Protocol) -> auto::Builder<TokioExecutor> {
+    let builder = auto::Builder::new(TokioExecutor::new());
+    match protocol {
+        Protocol::Auto => builder,
+        Protocol::Http1 => builder.http1_only(),
+        Protocol::Http2 => builder.http2_only(),
+    }
+}
+
+#[cfg(feature = "tls")]
+fn tls_acceptor(tls: &TlsConfig, protocol: Protocol) -> TlsAcceptor {
+    let mut config = tls.0.clone();
+    if config.alpn_protocols.is_empty() {
+        Arc::make_mut(&mut config).alpn_protocols = match protocol {
+            Protocol::Auto => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+            Protocol::Http1 => vec![b"http/1.1".to_vec()],
+            Protocol::Http2 => vec![b"h2".to_vec()],
+        };
+    }
+    return TlsAcceptor::from(config);
+}
+
+/// reads the PROXY header if enabled and works out the [`ConnectInfo`] of the connection
+async fn accept_connection<S>(
+    mut stream: Stream,
+    addrs: Option<(std::net::SocketAddr, std::net::SocketAddr)>,
+    shared: &Shared<S>,
+) -> Result<(Stream, Option<ConnectInfo>)> {
+    let mut info = addrs.map(|(remote_addr, local_addr)| ConnectInfo {
+        remote_addr,
+        local_addr,
+        proxy_addr: None,
+    });
+
+    if let Some(read_timeout) = shared.proxy_protocol {
+        let header = tokio::time::timeout(read_timeout, read_proxy_header(&mut stream))
+            .await
+            .context("timed out reading PROXY header")??;
+        if let Some(header) = header {
+            info = Some(ConnectInfo {
+                remote_addr: header.source,
+                local_addr: header.destination,
+                proxy_addr: info.map(|info| info.remote_addr),
+            });
+        }
+    }
+    return Ok((stream, info));
+}
+
+async fn serve_connection<I, S, B>(
+    io: I,
+    info: Option<ConnectInfo>,
+    shared: &Shared<S>,
+    builder: &auto::Builder<TokioExecutor>,
+    mut shutdown: watch::Receiver<()>,
+) -> Result<()>
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Service<Request<BoxBody>, Response = Response<B>> + Send + 'static,
+    S::Future: 'static + Send,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    let (io, activity) = IdleIo::new(io);
+    let service = TowerToHyperService::new(ConnectionService {
+        inner: shared.service.clone(),
+        info,
+        alt_svc: shared.alt_svc.clone(),
+        activity: activity.clone(),
+        span: Span::current(),
+    });
+
+    let conn = builder.serve_connection(TokioIo::new(io), service);
+    tokio::pin!(conn);
+    let mut shutting_down = false;
+    // set once the connection was found idle and asked to shut down gracefully
+    let mut grace_until = None;
+    loop {
+        let idle_deadline = shared.idle_timeout.map(|idle_timeout| {
+            activity
+                .deadline(idle_timeout)
+                .max(grace_until.unwrap_or_else(Instant::now))
+        });
+        tokio::select! {
+            res = conn.as_mut() => return res.map_err(|err| anyhow::anyhow!(err)),
+            _ = shutdown.changed(), if !shutting_down => {
+                conn.as_mut().graceful_shutdown();
+                shutting_down = true;
+            }
+            _ = tokio::time::sleep_until(idle_deadline.unwrap_or_else(Instant::now)), if idle_deadline.is_some() => {
+                let idle_timeout = shared.idle_timeout.unwrap_or_default();
+                // the deadline may have moved while we were sleeping
+                if activity.deadline(idle_timeout) > Instant::now() {
+                    continue;
+                }
+                if grace_until.is_some() {
+                    bail!("connection idle for {:?}, closing", idle_timeout);
+                }
+                if !shutting_down {
+                    conn.as_mut().graceful_shutdown();
+                    shutting_down = true;
+                }
+                grace_until = Some(Instant::now() + idle_timeout);
+            }
+        }
+    }
+}
+
+/// the service serving a single connection, which inserts its [`ConnectInfo`] into every
+/// request, boxes the request body, advertises http/3 if enabled and keeps the connection from
+/// timing out while a request is handled
+#[derive(Clone)]
+struct ConnectionService<S> {
+    inner: S,
+    info: Option<ConnectInfo>,
+    alt_svc: Option<HeaderValue>,
+    activity: Activity,
+    /// the connection span, requests of http/2 connections run in tasks of their own
+    span: Span,
+}
+
+impl<S, B> Service<Request<Incoming>> for ConnectionService<S>
+where
+    S: Service<Request<BoxBody>, Response = Response<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::result::Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Incoming>) -> Self::Future {
+        self.span.record("protocol", field::debug(req.version()));
+        let mut req = req.map(body::boxed);
+        if let Some(info) = self.info {
+            req.extensions_mut().insert(info);
+        }
+        let fut = self.inner.call(req);
+        let alt_svc = self.alt_svc.clone();
+        let guard = self.activity.request();
+        return Box::pin(async move {
+            let mut res = fut.await?;
+            drop(guard);
+            if let Some(alt_svc) = alt_svc {
+                res.headers_mut().insert(hyper::header::ALT_SVC, alt_svc);
+            }
+            Ok(res)
         });
     }
 }
 
 // somehow avoid wrapping all the middleware in a mutex? copying would be easier... but not all
 // services implement Clone
-struct ArcWrapper<T>(pub Arc<Mutex<T>>);
+//
+// a service panicking while the lock is held poisons it, the service is still used afterwards
+// instead of failing every later request
+pub(crate) struct ArcWrapper<T>(pub Arc<Mutex<T>>);
 
 impl<T> ArcWrapper<T> {
     pub fn new(data: T) -> Self {
         ArcWrapper(Arc::new(Mutex::new(data)))
     }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, T> {
+        return self.0.lock().unwrap_or_else(PoisonError::into_inner);
+    }
 }
 
 impl<T> Clone for ArcWrapper<T> {
@@ -73,9 +581,9 @@ impl<T> Clone for ArcWrapper<T> {
     }
 }
 
-impl<T> Service<Request<Incoming>> for ArcWrapper<T>
+impl<T> Service<Request<BoxBody>> for ArcWrapper<T>
 where
-    T: Service<Request<Incoming>>,
+    T: Service<Request<BoxBody>>,
 {
     type Response = T::Response;
     type Error = T::Error;
@@ -85,10 +593,209 @@ where
         &mut self,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<std::result::Result<(), Self::Error>> {
-        self.0.lock().unwrap().poll_ready(cx)
+        self.lock().poll_ready(cx)
     }
 
-    fn call(&mut self, req: Request<Incoming>) -> Self::Future {
-        self.0.lock().unwrap().call(req)
+    fn call(&mut self, req: Request<BoxBody>) -> Self::Future {
+        self.lock().call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Empty};
+    use hyper::client::conn::http1;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use tokio::net::{TcpListener, TcpStream};
+    use tower::service_fn;
+
+    type SendRequest = http1::SendRequest<Empty<Bytes>>;
+
+    /// responds with the name of the thread handling the request
+    async fn thread_name(_: Request<BoxBody>) -> Result<Response, Infallible> {
+        let name = std::thread::current()
+            .name()
+            .unwrap_or_default()
+            .to_string();
+        return Ok(Response::new(name));
+    }
+
+    fn loopback() -> Vec<Listener> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        return vec![TcpListener::from_std(listener).unwrap().into()];
+    }
+
+    /// serves `service` on the listeners returned by `bind` until the test ends, and returns
+    /// their stats
+    fn serve<S, B>(
+        bind: fn() -> Vec<Listener>,
+        service: S,
+        configure: fn(Serve<S>) -> Serve<S>,
+    ) -> Vec<Arc<AcceptorStats>>
+    where
+        S: Service<Request<BoxBody>, Response = Response<B>> + Send + Sync + 'static,
+        S::Future: 'static + Send,
+        S::Error: Into<Box<dyn Error + Send + Sync>>,
+        B: Body<Data = Bytes> + Send + 'static,
+        B::Error: Into<BoxError>,
+    {
+        let (tx, rx) = std::sync::mpsc::channel();
+        // `Serve` has to stay on the thread it was created on
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(async move {
+                let mut listeners = bind().into_iter();
+                let serve = Serve::new(listeners.next().unwrap(), service).listeners(listeners);
+                let serve = configure(serve);
+                tx.send(serve.stats()).unwrap();
+                serve.await.unwrap();
+            });
+        });
+        return rx.recv().unwrap();
+    }
+
+    async fn connect(addr: SocketAddr) -> SendRequest {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (sender, conn) = http1::handshake(TokioIo::new(stream)).await.unwrap();
+        tokio::spawn(conn);
+        return sender;
+    }
+
+    async fn get(sender: &mut SendRequest) -> String {
+        let req = hyper::Request::get("/").body(Empty::new()).unwrap();
+        let res = sender.send_request(req).await.unwrap();
+        assert_eq!(res.status(), 200);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        return String::from_utf8(body.to_vec()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn connection_limit() {
+        let stats = serve(loopback, service_fn(thread_name), |serve| {
+            serve.max_connections(1)
+        });
+        let stats = &stats[0];
+        let addr = stats.local_addr().unwrap();
+        let mut first = connect(addr).await;
+        get(&mut first).await;
+
+        // the second connection stays in the backlog until the first one is closed
+        let mut second = connect(addr).await;
+        let pending = tokio::spawn(async move { get(&mut second).await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!pending.is_finished());
+        assert_eq!(stats.accepted(), 1);
+
+        drop(first);
+        pending.await.unwrap();
+        assert_eq!(stats.accepted(), 2);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn thread_per_acceptor() {
+        let stats = serve(
+            || Listener::bind_reuse_port("127.0.0.1:0".parse().unwrap(), 2).unwrap(),
+            service_fn(thread_name),
+            |serve| serve.thread_per_acceptor(false),
+        );
+        let addr = stats[0].local_addr().unwrap();
+        assert_eq!(stats[1].local_addr(), Some(addr));
+
+        let mut threads = std::collections::BTreeSet::new();
+        for _ in 0..32 {
+            threads.insert(get(&mut connect(addr).await).await);
+        }
+        let threads: Vec<_> = threads.into_iter().collect();
+        assert_eq!(threads, ["axtel-acceptor-0", "axtel-acceptor-1"]);
+        assert_eq!(stats[0].accepted() + stats[1].accepted(), 32);
+    }
+
+    #[cfg(unix)]
+    const BACKOFF_CHILD: &str = "AXTEL_BACKOFF_CHILD";
+
+    /// the process spawned by `accept_backoff`, which runs out of file descriptors before
+    /// serving the listener handed to it, and gets them back after a few failed accepts
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn accept_backoff_child() {
+        use std::os::fd::AsRawFd;
+
+        if std::env::var_os(BACKOFF_CHILD).is_none() {
+            return;
+        }
+        let listener = Listener::from_env().unwrap().pop().unwrap();
+        // only this process is limited, so it runs out quickly
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        assert_eq!(
+            unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) },
+            0
+        );
+        limit.rlim_cur = limit.rlim_cur.min(256);
+        assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) }, 0);
+        let mut fds = Vec::new();
+        loop {
+            let fd = unsafe { libc::dup(listener.as_raw_fd()) };
+            if fd < 0 {
+                break;
+            }
+            fds.push(fd);
+        }
+
+        let serve = Serve::new(listener, service_fn(thread_name));
+        let stats = serve.stats().remove(0);
+        let signal = {
+            let stats = stats.clone();
+            async move {
+                while stats.accept_errors() < 3 && stats.accepted() == 0 {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+                for fd in fds {
+                    unsafe { libc::close(fd) };
+                }
+                while stats.accepted() == 0 || stats.active() > 0 {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            }
+        };
+        serve.with_graceful_shutdown(signal).await.unwrap();
+        assert!(stats.accept_errors() >= 3);
+        assert_eq!(stats.accepted(), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn accept_backoff() {
+        use crate::server::activation::hand_off;
+
+        let listener = loopback().remove(0);
+        let mut command = std::process::Command::new(std::env::current_exe().unwrap());
+        command
+            .args([
+                "server::serve::tests::accept_backoff_child",
+                "--exact",
+                "-q",
+            ])
+            .env(BACKOFF_CHILD, "1");
+        let mut child = hand_off(&[&listener], command).unwrap();
+
+        // the connection waits in the backlog until the child has file descriptors again
+        let mut sender = connect(listener.local_addr().unwrap()).await;
+        get(&mut sender).await;
+        drop(sender);
+        let status = tokio::task::spawn_blocking(move || child.wait())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(status.success());
     }
 }
@@ -0,0 +1,62 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// counters of a single accept loop of [`Serve`](crate::server::serve::Serve)
+#[derive(Debug)]
+pub struct AcceptorStats {
+    local_addr: Option<SocketAddr>,
+    name: Option<String>,
+    accepted: AtomicU64,
+    active: AtomicU64,
+    accept_errors: AtomicU64,
+}
+
+impl AcceptorStats {
+    pub(crate) fn new(local_addr: Option<SocketAddr>, name: Option<String>) -> Self {
+        return Self {
+            local_addr,
+            name,
+            accepted: AtomicU64::new(0),
+            active: AtomicU64::new(0),
+            accept_errors: AtomicU64::new(0),
+        };
+    }
+
+    /// the address of the listener, if it is a tcp listener
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        return self.local_addr;
+    }
+
+    /// the socket activation name of the listener
+    pub fn name(&self) -> Option<&str> {
+        return self.name.as_deref();
+    }
+
+    /// connections accepted since the server started
+    pub fn accepted(&self) -> u64 {
+        return self.accepted.load(Ordering::Relaxed);
+    }
+
+    /// connections currently open
+    pub fn active(&self) -> u64 {
+        return self.active.load(Ordering::Relaxed);
+    }
+
+    /// failed calls to `accept`, not counting connections aborted by the client
+    pub fn accept_errors(&self) -> u64 {
+        return self.accept_errors.load(Ordering::Relaxed);
+    }
+
+    pub(crate) fn connection_opened(&self) {
+        self.accepted.fetch_add(1, Ordering::Relaxed);
+        self.active.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connection_closed(&self) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn accept_failed(&self) {
+        self.accept_errors.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,38 @@
+use anyhow::{anyhow, Context, Result};
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+use tokio_rustls::rustls::ServerConfig;
+
+/// tls settings for a [`Listener`](crate::server::listener::Listener)
+#[derive(Clone)]
+pub struct TlsConfig(pub(crate) Arc<ServerConfig>);
+
+impl TlsConfig {
+    /// loads a certificate chain and private key from pem encoded files
+    pub fn from_pem_files(cert: impl AsRef<Path>, key: impl AsRef<Path>) -> Result<Self> {
+        let cert = cert.as_ref();
+        let key = key.as_ref();
+        let mut reader = BufReader::new(
+            File::open(cert).with_context(|| format!("failed to open {}", cert.display()))?,
+        );
+        let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+
+        let mut reader = BufReader::new(
+            File::open(key).with_context(|| format!("failed to open {}", key.display()))?,
+        );
+        let key = rustls_pemfile::private_key(&mut reader)?
+            .ok_or_else(|| anyhow!("no private key found in {}", key.display()))?;
+
+        let config = ServerConfig::builder_with_provider(Arc::new(
+            tokio_rustls::rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+        return Ok(Self(Arc::new(config)));
+    }
+
+    /// use a custom rustls config, alpn protocols are filled in by axtel if left empty
+    pub fn from_config(config: Arc<ServerConfig>) -> Self {
+        return Self(config);
+    }
+}